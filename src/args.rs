@@ -1,8 +1,23 @@
-use clap::{Parser, Subcommand, ValueEnum};
-use color_eyre::{Result, eyre::ContextCompat};
+use crate::{
+    config::{Config, profile_from_args},
+    fmt::parse_rate,
+    utils::{checksum::Checksum, input_file::parse_input_file, schedule::Schedule},
+};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, builder::Resettable};
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, bail},
+};
 use crossterm::terminal;
-use reqwest::header::{HeaderMap, HeaderName};
-use std::{path::PathBuf, str::FromStr, time::Duration};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum WriteMethod {
@@ -10,6 +25,54 @@ pub enum WriteMethod {
     Std,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// 标准错误为终端时使用 bar, 否则使用 plain
+    Auto,
+    /// 在终端中绘制进度条
+    Bar,
+    /// 在进度条下方列出每个线程的区间、速度、重试次数和状态
+    Detailed,
+    /// 每隔一段时间或一定进度输出一行, 不使用控制字符
+    Plain,
+    /// 不输出进度
+    None,
+    /// 向标准错误输出 NDJSON, 每次重绘输出进度, 并输出下载事件
+    Json,
+    /// 全屏界面, 显示分块图、速度曲线、各线程状态和日志
+    Tui,
+}
+
+impl ProgressMode {
+    /// 把 auto 换成实际使用的方式
+    pub fn resolve(self) -> Self {
+        match self {
+            Self::Auto if io::stderr().is_terminal() => Self::Bar,
+            Self::Auto => Self::Plain,
+            mode => mode,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// 便于阅读的表格
+    Table,
+    /// 包含所有记录的 JSON 数组
+    Json,
+    /// 每行一条记录的 JSON
+    Ndjson,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    Path,
+    Size,
+    Progress,
+    Elapsed,
+}
+
 /// 超级快的下载器
 #[derive(Parser, Debug)]
 #[command(name = "fast-down")]
@@ -36,88 +99,289 @@ enum Commands {
     // Update,
     /// 显示数据库
     List(ListCli),
+    /// 继续数据库中未完成的下载
+    Resume(ResumeCli),
+    /// 删除数据库中的下载记录
+    #[command(visible_alias = "forget")]
+    Remove(RemoveCli),
+    /// 导出或导入数据库中的下载记录
+    #[command(subcommand)]
+    State(StateCommands),
+    /// 显示已结束的下载
+    History(HistoryCli),
+    /// 显示下载统计
+    Stats(StatsCli),
+}
+
+/// 管理下载记录的命令共用的数据库选项
+#[derive(clap::Args, Debug)]
+struct StateDbCli {
+    /// 数据库路径, 默认使用当前目录中的 .fd-state.db 或 $XDG_STATE_HOME/fast-down 中的数据库
+    #[arg(long, value_name = "PATH", env = "FD_STATE_DB")]
+    state_db: Option<PathBuf>,
+}
+
+/// 下载与续传共用的并发选项, 传入多个文件时同时下载并共享 --threads 的线程预算
+#[derive(clap::Args, Debug)]
+struct JobsCli {
+    /// 同时下载的文件数
+    #[arg(short, long, default_value_t = 3, env = "FD_JOBS")]
+    jobs: usize,
+}
+
+#[derive(Subcommand, Debug)]
+enum StateCommands {
+    /// 以 JSON 格式导出所有下载记录
+    Export(StateExportCli),
+    /// 从 JSON 文件导入下载记录
+    Import(StateImportCli),
+}
+
+#[derive(clap::Args, Debug)]
+struct StateExportCli {
+    /// 输出文件, 默认输出到标准输出
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    db: StateDbCli,
+}
+
+#[derive(clap::Args, Debug)]
+struct StateImportCli {
+    /// 导出的 JSON 文件 (- 为标准输入)
+    input: PathBuf,
+    /// 改写路径前缀, 如 /home/a=/data (可多次使用)
+    #[arg(long, value_name = "OLD=NEW")]
+    rebase: Vec<String>,
+    /// 覆盖数据库中已有的记录
+    #[arg(long)]
+    overwrite: bool,
+    #[command(flatten)]
+    db: StateDbCli,
 }
 
 #[derive(clap::Args, Debug)]
 struct DownloadCli {
-    /// 要下载的URL
-    #[arg(required = true)]
-    url: String,
+    /// 要下载的URL (可传入多个)
+    #[arg(required_unless_present = "input_file")]
+    urls: Vec<String>,
+    /// 从文件读取 URL 列表, 每行一个 (- 为标准输入)
+    #[arg(long, value_name = "FILE", env = "FD_INPUT_FILE")]
+    input_file: Option<PathBuf>,
+    /// 使用配置文件中 [profile.<NAME>] 的选项
+    #[arg(long, value_name = "NAME", env = "FD_PROFILE")]
+    profile: Option<String>,
+    #[command(flatten)]
+    jobs: JobsCli,
     /// 强制覆盖已有文件
-    #[arg(short, long)]
+    #[arg(short, long, env = "FD_FORCE")]
     force: bool,
     /// 禁止断点续传
-    #[arg(long)]
+    #[arg(long, env = "FD_NO_RESUME")]
     no_resume: bool,
     /// 保存目录
-    #[arg(short = 'd', long = "dir", default_value = ".")]
+    #[arg(short = 'd', long = "dir", default_value = ".", env = "FD_DIR")]
     save_folder: PathBuf,
     /// 下载线程数
-    #[arg(short, long, default_value_t = 32)]
+    #[arg(short, long, default_value_t = 32, env = "FD_THREADS")]
     threads: usize,
     /// 自定义文件名
-    #[arg(short = 'o', long = "out")]
+    #[arg(short = 'o', long = "out", env = "FD_OUT")]
     file_name: Option<String>,
+    /// 内容相同的镜像地址, 与主链接一起分片下载 (可多次使用, 或用逗号分隔)
+    #[arg(
+        short,
+        long = "mirror",
+        value_name = "URL",
+        value_delimiter = ',',
+        env = "FD_MIRROR"
+    )]
+    mirrors: Vec<String>,
+    /// 下载完成后校验文件 (支持 sha256, sha1, md5, blake3)
+    #[arg(long, value_name = "ALGO=HEX", env = "FD_CHECKSUM")]
+    checksum: Option<String>,
+    /// 从校验文件 (如 SHA256SUMS) 中获取校验值
+    #[arg(long, value_name = "URL", env = "FD_CHECKSUM_URL")]
+    checksum_url: Option<String>,
+    /// 自动查找并校验同目录下的 <文件名>.sha256 或 SHA256SUMS
+    #[arg(long, env = "FD_VERIFY_SIDECAR")]
+    verify_sidecar: bool,
+    /// 限制总下载速度, 如 500K、5M, 0 为不限速 (运行时可用 SIGUSR1/SIGUSR2 减半/加倍)
+    #[arg(long, value_name = "SPEED", default_value = "0", env = "FD_LIMIT_RATE")]
+    limit_rate: String,
+    /// 按时间段限速, 如 "08:00-18:00=2M,18:00-08:00=unlimited", 不在任何时间段内时使用 --limit-rate
+    #[arg(long, value_name = "SCHEDULE", env = "FD_SCHEDULE")]
+    schedule: Option<String>,
     /// 代理地址 (格式: http://proxy:port 或 socks5://proxy:port) 不填为使用系统代理，-p "" 为不使用代理
-    #[arg(short, long)]
+    #[arg(short, long, env = "FD_PROXY")]
     proxy: Option<String>,
-    /// 自定义请求头 (可多次使用)
-    #[arg(short = 'H', long = "header", value_name = "Key: Value")]
+    /// 自定义请求头 (可多次使用, 请求头中可能含有逗号, 因此环境变量中用换行分隔)
+    #[arg(
+        short = 'H',
+        long = "header",
+        value_name = "Key: Value",
+        value_delimiter = '\n',
+        env = "FD_HEADER"
+    )]
     headers: Vec<String>,
     /// 块平滑窗口 (单位: B)
-    #[arg(long, default_value_t = 8 * 1024)]
+    #[arg(long, default_value_t = 8 * 1024, env = "FD_CHUNK_WINDOW")]
     chunk_window: u64,
     /// 最小分片大小 (单位: B)
-    #[arg(long, default_value_t = 1024 * 1024)]
+    #[arg(long, default_value_t = 1024 * 1024, env = "FD_MIN_CHUNK_SIZE")]
     min_chunk_size: u64,
     /// 写入缓冲区大小 (单位: B)
-    #[arg(long, default_value_t = 8 * 1024 * 1024)]
+    #[arg(long, default_value_t = 8 * 1024 * 1024, env = "FD_WRITE_BUFFER_SIZE")]
     write_buffer_size: usize,
     /// 写入通道长度
-    #[arg(long, default_value_t = 10240)]
+    #[arg(long, default_value_t = 10240, env = "FD_WRITE_QUEUE_CAP")]
     write_queue_cap: usize,
     /// 进度条显示宽度
-    #[arg(long)]
+    #[arg(long, env = "FD_PROGRESS_WIDTH")]
     progress_width: Option<u16>,
+    /// 进度输出方式
+    #[arg(long, default_value = "auto", env = "FD_PROGRESS")]
+    progress: ProgressMode,
+    /// 等同于 --progress tui, 优先于 --progress
+    #[arg(long, env = "FD_TUI")]
+    tui: bool,
+    /// 收到 SIGTSTP (Ctrl-Z) 时暂停下载而不是挂起进程, 收到 SIGCONT 时继续; 全屏界面中默认开启
+    #[arg(long, env = "FD_PAUSE_ON_TSTP")]
+    pause_on_tstp: bool,
+    /// plain 模式下输出进度的间隔 (单位: s)
+    #[arg(long, default_value_t = 10, env = "FD_PLAIN_INTERVAL")]
+    plain_interval: u64,
+    /// plain 模式下进度每增加多少百分比输出一次
+    #[arg(long, default_value_t = 10.0, env = "FD_PLAIN_STEP")]
+    plain_step: f64,
     /// 重试间隔 (单位: ms)
-    #[arg(long, default_value_t = 500)]
+    #[arg(long, default_value_t = 500, env = "FD_RETRY_GAP")]
     retry_gap: u64,
     /// 进度条重绘间隔 (单位: ms)
-    #[arg(long, default_value_t = 200)]
+    #[arg(long, default_value_t = 200, env = "FD_REPAINT_GAP")]
     repaint_gap: u64,
     /// 拉取超时时间 (单位: ms)
-    #[arg(long, default_value_t = 5000)]
+    #[arg(long, default_value_t = 5000, env = "FD_PULL_TIMEOUT")]
     pull_timeout: u64,
     /// 模拟浏览器行为
-    #[arg(long)]
+    #[arg(long, env = "FD_BROWSER")]
     browser: bool,
     /// 全部确认
-    #[arg(short, long)]
+    #[arg(short, long, env = "FD_YES")]
     yes: bool,
     /// 详细输出
-    #[arg(short, long)]
+    #[arg(short, long, env = "FD_VERBOSE")]
     verbose: bool,
     /// 允许无效证书
-    #[arg(long)]
+    #[arg(long, env = "FD_ACCEPT_INVALID_CERTS")]
     accept_invalid_certs: bool,
     /// 允许无效主机名
-    #[arg(long)]
+    #[arg(long, env = "FD_ACCEPT_INVALID_HOSTNAMES")]
     accept_invalid_hostnames: bool,
     /// 是否使用交互式界面选择网卡
-    #[arg(short, long)]
+    #[arg(short, long, env = "FD_INTERFACE")]
     interface: bool,
-    /// 自定义网卡 (可多次使用)
-    #[arg(long = "ip", value_name = "网卡的 ip 地址")]
-    ips: Vec<String>,
+    /// 自定义网卡 (可多次使用, 或用逗号分隔)
+    #[arg(
+        long = "ip",
+        value_name = "网卡的 ip 地址",
+        value_delimiter = ',',
+        env = "FD_IP"
+    )]
+    ips: Vec<IpAddr>,
     /// 最大投机线程数
-    #[arg(long, default_value_t = 3)]
+    #[arg(long, default_value_t = 3, env = "FD_MAX_SPECULATIVE")]
     max_speculative: usize,
     /// 写入方法 (mmap 速度快, std 兼容性好)
-    #[arg(long, default_value = "mmap")]
+    #[arg(long, default_value = "mmap", env = "FD_WRITE_METHOD")]
     write_method: WriteMethod,
     /// 是否启用文件预分配
-    #[arg(long)]
+    #[arg(long, env = "FD_PRE_ALLOC")]
     pre_alloc: bool,
+    /// 数据库路径, 默认使用下载目录中的 .fd-state.db 或 $XDG_STATE_HOME/fast-down 中的数据库
+    #[arg(long, value_name = "PATH", env = "FD_STATE_DB")]
+    state_db: Option<PathBuf>,
+    /// 把下载记录保存在下载目录的 .fd-state.db 中
+    #[arg(long, env = "FD_PROJECT_STATE")]
+    project_state: bool,
+    /// 同时把续传信息写入 <文件>.fdpart.meta, 便于在不同机器间移动未完成的文件
+    #[arg(long, env = "FD_SIDECAR_STATE")]
+    sidecar_state: bool,
+}
+
+/// 不含下载链接的下载参数, 默认值同样遵循环境变量与配置文件
+fn default_download_args(default_cmd: clap::Command) -> Result<DownloadArgs> {
+    let matches = default_cmd
+        .mut_arg("urls", |arg| arg.required_unless_present(Resettable::Reset))
+        .try_get_matches_from(["fast-down"])?;
+    CliDefault::from_arg_matches(&matches)?
+        .cmd
+        .to_download_args()
+}
+
+impl DownloadCli {
+    /// 转换为单个文件的下载参数, 下载链接取第一个 URL
+    fn to_download_args(&self) -> Result<DownloadArgs> {
+        let mut args = DownloadArgs {
+            url: self.urls.first().cloned().unwrap_or_default(),
+            mirrors: self.mirrors.clone(),
+            checksum: self.checksum.as_deref().map(str::parse).transpose()?,
+            checksum_url: self.checksum_url.clone(),
+            verify_sidecar: self.verify_sidecar,
+            limit_rate: parse_rate(&self.limit_rate)
+                .with_context(|| format!("速度格式错误: {}", self.limit_rate))?,
+            schedule: None,
+            force: self.force,
+            resume: !self.no_resume,
+            save_folder: self.save_folder.clone(),
+            threads: self.threads,
+            file_name: self.file_name.clone(),
+            proxy: self.proxy.clone(),
+            headers: HeaderMap::new(),
+            chunk_window: self.chunk_window,
+            min_chunk_size: self.min_chunk_size,
+            write_buffer_size: self.write_buffer_size,
+            write_queue_cap: self.write_queue_cap,
+            progress_width: terminal::size()
+                .ok()
+                .and_then(|s| s.0.checked_sub(36))
+                .unwrap_or(50),
+            progress: if self.tui {
+                ProgressMode::Tui
+            } else {
+                self.progress.resolve()
+            },
+            pause_on_tstp: self.pause_on_tstp,
+            plain_interval: Duration::from_secs(self.plain_interval),
+            plain_step: self.plain_step,
+            retry_gap: Duration::from_millis(self.retry_gap),
+            repaint_gap: Duration::from_millis(self.repaint_gap),
+            pull_timeout: Duration::from_millis(self.pull_timeout),
+            browser: self.browser,
+            yes: self.yes,
+            verbose: self.verbose,
+            accept_invalid_certs: self.accept_invalid_certs,
+            accept_invalid_hostnames: self.accept_invalid_hostnames,
+            interface: self.interface,
+            ips: self.ips.iter().map(IpAddr::to_string).collect(),
+            max_speculative: self.max_speculative,
+            write_method: self.write_method.clone(),
+            pre_alloc: self.pre_alloc,
+            state_db: self.state_db.clone(),
+            project_state: self.project_state,
+            sidecar_state: self.sidecar_state,
+        };
+        if let Some(schedule) = &self.schedule {
+            let mut schedule: Schedule = schedule.parse()?;
+            schedule.default_rate = args.limit_rate;
+            args.schedule = Some(schedule);
+        }
+        for header in &self.headers {
+            let (name, value) = parse_header(header)?;
+            args.headers.insert(name, value);
+        }
+        Ok(args)
+    }
 }
 
 #[derive(clap::Args, Debug)]
@@ -125,19 +389,109 @@ struct ListCli {
     /// 是否显示详细信息
     #[arg(short, long)]
     details: bool,
+    /// 输出格式
+    #[arg(long, default_value = "table")]
+    format: ListFormat,
+    /// 等同于 --format json
+    #[arg(long, conflicts_with = "format")]
+    json: bool,
+    /// 每条记录只显示一行, 并用进度条显示已下载的区间
+    #[arg(short, long, conflicts_with = "details")]
+    compact: bool,
+    /// 排序方式
+    #[arg(long, default_value = "path")]
+    sort: ListSort,
+    /// 筛选条件, 如 host=example.com (可多次使用)
+    #[arg(long, value_name = "KEY=VALUE")]
+    filter: Vec<String>,
+    /// 只显示未下载完成的记录
+    #[arg(long)]
+    incomplete_only: bool,
+    /// 只显示该目录下的记录
+    #[arg(long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+    #[command(flatten)]
+    db: StateDbCli,
+}
+
+#[derive(clap::Args, Debug)]
+struct HistoryCli {
+    /// 最多显示的条数, 0 为不限制
+    #[arg(short = 'n', long, default_value_t = 20)]
+    limit: usize,
+    /// 只显示失败的下载
+    #[arg(long)]
+    failed: bool,
+    #[command(flatten)]
+    db: StateDbCli,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatsCli {
+    #[command(flatten)]
+    db: StateDbCli,
+}
+
+#[derive(clap::Args, Debug)]
+struct ResumeCli {
+    /// 要继续的文件路径或 `fd list` 中的序号
+    #[arg(required_unless_present = "all")]
+    target: Option<String>,
+    /// 继续所有未完成的下载
+    #[arg(long, conflicts_with = "target")]
+    all: bool,
+    #[command(flatten)]
+    jobs: JobsCli,
+    /// 全部确认
+    #[arg(short, long)]
+    yes: bool,
+    #[command(flatten)]
+    db: StateDbCli,
+}
+
+#[derive(clap::Args, Debug)]
+struct RemoveCli {
+    /// 要删除的文件路径、通配符或 `fd list` 中的序号 (可传入多个)
+    #[arg(required_unless_present = "all")]
+    targets: Vec<String>,
+    /// 删除所有记录
+    #[arg(long, conflicts_with = "targets")]
+    all: bool,
+    /// 同时删除未完成的文件
+    #[arg(long)]
+    delete_files: bool,
+    /// 全部确认
+    #[arg(short, long)]
+    yes: bool,
+    #[command(flatten)]
+    db: StateDbCli,
 }
 
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum Args {
     Download(DownloadArgs),
+    Batch(BatchArgs),
     // Update,
     List(ListArgs),
+    Resume(ResumeArgs),
+    Remove(RemoveArgs),
+    StateExport(StateExportArgs),
+    StateImport(StateImportArgs),
+    History(HistoryArgs),
+    Stats(StatsArgs),
 }
 
 #[derive(Debug, Clone)]
 pub struct DownloadArgs {
     pub url: String,
+    pub mirrors: Vec<String>,
+    pub checksum: Option<Checksum>,
+    pub checksum_url: Option<String>,
+    pub verify_sidecar: bool,
+    /// 限速 (单位: B/s), 0 代表不限速
+    pub limit_rate: u64,
+    pub schedule: Option<Schedule>,
     pub force: bool,
     pub resume: bool,
     pub save_folder: PathBuf,
@@ -151,6 +505,12 @@ pub struct DownloadArgs {
     pub write_queue_cap: usize,
     pub repaint_gap: Duration,
     pub progress_width: u16,
+    pub progress: ProgressMode,
+    /// 全屏界面中无论是否指定都会开启
+    pub pause_on_tstp: bool,
+    pub plain_interval: Duration,
+    /// 单位: %
+    pub plain_step: f64,
     pub retry_gap: Duration,
     pub pull_timeout: Duration,
     pub browser: bool,
@@ -163,75 +523,282 @@ pub struct DownloadArgs {
     pub max_speculative: usize,
     pub write_method: WriteMethod,
     pub pre_alloc: bool,
+    pub state_db: Option<PathBuf>,
+    pub project_state: bool,
+    pub sidecar_state: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchArgs {
+    pub tasks: Vec<DownloadArgs>,
+    pub jobs: usize,
+    /// 所有文件共享的线程数
+    pub threads: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct ListArgs {
     pub details: bool,
+    pub format: ListFormat,
+    pub compact: bool,
+    pub sort: ListSort,
+    /// 为空时不按域名筛选
+    pub hosts: Vec<String>,
+    pub incomplete_only: bool,
+    pub dir: Option<PathBuf>,
+    pub state_db: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryArgs {
+    /// 为 None 时不限制条数
+    pub limit: Option<usize>,
+    pub failed: bool,
+    pub state_db: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StatsArgs {
+    pub state_db: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResumeArgs {
+    /// 为 None 时继续所有下载
+    pub target: Option<String>,
+    pub jobs: usize,
+    /// 数据库中没有记录的选项使用的默认值
+    pub template: DownloadArgs,
+    pub state_db: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoveArgs {
+    pub targets: Vec<String>,
+    pub all: bool,
+    pub delete_files: bool,
+    pub yes: bool,
+    pub state_db: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StateExportArgs {
+    pub output: Option<PathBuf>,
+    pub state_db: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StateImportArgs {
+    pub input: PathBuf,
+    /// `(旧前缀, 新前缀)`
+    pub rebase: Vec<(PathBuf, PathBuf)>,
+    pub overwrite: bool,
+    pub state_db: Option<PathBuf>,
 }
 
 impl Args {
     pub fn parse() -> Result<Args> {
-        match Cli::try_parse().or_else(|err| match err.kind() {
-            clap::error::ErrorKind::InvalidSubcommand | clap::error::ErrorKind::UnknownArgument => {
-                CliDefault::try_parse().map(|cli_default| Cli {
-                    command: Commands::Download(cli_default.cmd),
-                })
+        // 配置文件中的选项作为默认值, 因此优先级为 命令行 > 环境变量 > 配置文件 > 内置默认值
+        let cli_cmd = Cli::command();
+        let download_cmd = cli_cmd
+            .find_subcommand("download")
+            .cloned()
+            .with_context(|| "缺少 download 子命令")?;
+        // 配置文件只用于 download 与 resume, 有误时其他命令照常运行
+        let configured = Config::load(profile_from_args().as_deref()).and_then(|config| {
+            Ok((
+                config.apply(download_cmd.clone())?,
+                config.apply(CliDefault::command())?,
+            ))
+        });
+        let (download_cmd, default_cmd, config_err) = match configured {
+            Ok((download_cmd, default_cmd)) => (download_cmd, default_cmd, None),
+            Err(err) => (download_cmd, CliDefault::command(), Some(err)),
+        };
+        let cli_cmd = cli_cmd.mut_subcommand("download", |_| download_cmd);
+        let parsed = cli_cmd
+            .try_get_matches()
+            .and_then(|matches| Cli::from_arg_matches(&matches))
+            .or_else(|err| match err.kind() {
+                clap::error::ErrorKind::InvalidSubcommand
+                | clap::error::ErrorKind::UnknownArgument => default_cmd
+                    .clone()
+                    .try_get_matches()
+                    .and_then(|matches| CliDefault::from_arg_matches(&matches))
+                    .map(|cli_default| Cli {
+                        command: Commands::Download(cli_default.cmd),
+                    }),
+                _ => Err(err),
+            });
+        if let (Ok(cli), Some(err)) = (&parsed, config_err) {
+            if matches!(cli.command, Commands::Download(_) | Commands::Resume(_)) {
+                return Err(err);
             }
-            _ => Err(err),
-        }) {
+            eprintln!("已忽略配置文件: {err:#}");
+        }
+        match parsed {
             Ok(cli) => match cli.command {
                 Commands::Download(cli) => {
-                    let mut args = DownloadArgs {
-                        url: cli.url,
-                        force: cli.force,
-                        resume: !cli.no_resume,
-                        save_folder: cli.save_folder,
-                        threads: cli.threads,
-                        file_name: cli.file_name,
-                        proxy: cli.proxy,
-                        headers: HeaderMap::new(),
-                        chunk_window: cli.chunk_window,
-                        min_chunk_size: cli.min_chunk_size,
-                        write_buffer_size: cli.write_buffer_size,
-                        write_queue_cap: cli.write_queue_cap,
-                        progress_width: terminal::size()
-                            .ok()
-                            .and_then(|s| s.0.checked_sub(36))
-                            .unwrap_or(50),
-                        retry_gap: Duration::from_millis(cli.retry_gap),
-                        repaint_gap: Duration::from_millis(cli.repaint_gap),
-                        pull_timeout: Duration::from_millis(cli.pull_timeout),
-                        browser: cli.browser,
-                        yes: cli.yes,
-                        verbose: cli.verbose,
-                        accept_invalid_certs: cli.accept_invalid_certs,
-                        accept_invalid_hostnames: cli.accept_invalid_hostnames,
-                        interface: cli.interface,
-                        ips: cli.ips,
-                        max_speculative: cli.max_speculative,
-                        write_method: cli.write_method,
-                        pre_alloc: cli.pre_alloc,
+                    let args = cli.to_download_args()?;
+                    if cli.urls.len() == 1 && cli.input_file.is_none() {
+                        return Ok(Args::Download(args));
+                    }
+                    if args.file_name.is_some() {
+                        bail!("下载多个文件时不能使用 --out, 请在输入文件中使用 out= 选项");
+                    }
+                    if !args.mirrors.is_empty() {
+                        bail!("下载多个文件时不能使用 --mirror, 请在输入文件中用 TAB 分隔镜像");
+                    }
+                    if args.checksum.is_some() {
+                        bail!(
+                            "下载多个文件时不能使用 --checksum, 请在输入文件中使用 checksum= 选项"
+                        );
+                    }
+                    if args.checksum_url.is_some() {
+                        bail!(
+                            "下载多个文件时不能使用 --checksum-url, 请在输入文件中使用 checksum-url= 选项"
+                        );
+                    }
+                    let mut tasks: Vec<_> = cli
+                        .urls
+                        .into_iter()
+                        .map(|url| DownloadArgs {
+                            url,
+                            ..args.clone()
+                        })
+                        .collect();
+                    let content = match cli.input_file {
+                        Some(input_file) if input_file.as_os_str() == "-" => {
+                            io::read_to_string(io::stdin())?
+                        }
+                        Some(input_file) => fs::read_to_string(&input_file)?,
+                        None => String::new(),
                     };
-                    for header in cli.headers {
-                        let mut parts = header.splitn(2, ':').map(|t| t.trim());
-                        let name = parts
-                            .next()
-                            .with_context(|| format!("请求头格式错误: {header}"))?;
-                        let value = parts
-                            .next()
-                            .with_context(|| format!("请求头格式错误: {header}"))?;
-                        args.headers
-                            .insert(HeaderName::from_str(name)?, value.parse()?);
+                    for entry in parse_input_file(&content)? {
+                        let mut task = args.clone();
+                        task.url = entry.url;
+                        task.mirrors = entry.mirrors;
+                        for (key, value) in entry.options {
+                            match key.as_str() {
+                                "out" => task.file_name = Some(value),
+                                "dir" => task.save_folder = PathBuf::from(value),
+                                "header" => {
+                                    let (name, value) = parse_header(&value)?;
+                                    task.headers.insert(name, value);
+                                }
+                                "all-proxy" => task.proxy = Some(value),
+                                "checksum" => task.checksum = Some(value.parse()?),
+                                "checksum-url" => task.checksum_url = Some(value),
+                                _ => bail!("输入文件中不支持的选项: {key}"),
+                            }
+                        }
+                        tasks.push(task);
                     }
-                    Ok(Args::Download(args))
+                    Ok(Args::Batch(BatchArgs {
+                        tasks,
+                        jobs: cli.jobs.jobs.max(1),
+                        threads: cli.threads,
+                    }))
                 }
                 // Commands::Update => Ok(Args::Update),
-                Commands::List(cli) => Ok(Args::List(ListArgs {
-                    details: cli.details,
+                Commands::List(cli) => {
+                    let mut hosts = Vec::new();
+                    for filter in &cli.filter {
+                        let (key, value) = filter
+                            .split_once('=')
+                            .with_context(|| format!("筛选条件格式错误: {filter}"))?;
+                        match key.trim() {
+                            "host" => hosts.push(value.trim().to_ascii_lowercase()),
+                            key => bail!("不支持的筛选条件: {key}"),
+                        }
+                    }
+                    Ok(Args::List(ListArgs {
+                        details: cli.details,
+                        format: if cli.json {
+                            ListFormat::Json
+                        } else {
+                            cli.format
+                        },
+                        compact: cli.compact,
+                        sort: cli.sort,
+                        hosts,
+                        incomplete_only: cli.incomplete_only,
+                        dir: cli.dir,
+                        state_db: cli.db.state_db,
+                    }))
+                }
+                Commands::Resume(cli) => {
+                    let mut template = default_download_args(default_cmd)?;
+                    template.yes |= cli.yes;
+                    Ok(Args::Resume(ResumeArgs {
+                        target: cli.target.filter(|_| !cli.all),
+                        jobs: cli.jobs.jobs.max(1),
+                        template,
+                        state_db: cli.db.state_db,
+                    }))
+                }
+                Commands::Remove(cli) => Ok(Args::Remove(RemoveArgs {
+                    targets: cli.targets,
+                    all: cli.all,
+                    delete_files: cli.delete_files,
+                    yes: cli.yes,
+                    state_db: cli.db.state_db,
+                })),
+                Commands::State(StateCommands::Export(cli)) => {
+                    Ok(Args::StateExport(StateExportArgs {
+                        output: cli.output,
+                        state_db: cli.db.state_db,
+                    }))
+                }
+                Commands::State(StateCommands::Import(cli)) => {
+                    let rebase = cli
+                        .rebase
+                        .iter()
+                        .map(|rule| {
+                            let (old, new) = rule
+                                .split_once('=')
+                                .with_context(|| format!("路径替换格式错误: {rule}"))?;
+                            Ok((PathBuf::from(old), PathBuf::from(new)))
+                        })
+                        .collect::<Result<_>>()?;
+                    Ok(Args::StateImport(StateImportArgs {
+                        input: cli.input,
+                        rebase,
+                        overwrite: cli.overwrite,
+                        state_db: cli.db.state_db,
+                    }))
+                }
+                Commands::History(cli) => Ok(Args::History(HistoryArgs {
+                    limit: (cli.limit > 0).then_some(cli.limit),
+                    failed: cli.failed,
+                    state_db: cli.db.state_db,
+                })),
+                Commands::Stats(cli) => Ok(Args::Stats(StatsArgs {
+                    state_db: cli.db.state_db,
                 })),
             },
             Err(err) => err.exit(),
         }
     }
+
+    /// 下载时使用的进度输出方式, 其他命令为 None
+    pub fn progress(&self) -> Option<ProgressMode> {
+        match self {
+            Args::Download(args) => Some(args.progress),
+            Args::Batch(args) => args.tasks.first().map(|task| task.progress),
+            Args::Resume(args) => Some(args.template.progress),
+            _ => None,
+        }
+    }
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue)> {
+    let mut parts = header.splitn(2, ':').map(|t| t.trim());
+    let name = parts
+        .next()
+        .with_context(|| format!("请求头格式错误: {header}"))?;
+    let value = parts
+        .next()
+        .with_context(|| format!("请求头格式错误: {header}"))?;
+    Ok((HeaderName::from_str(name)?, value.parse()?))
 }
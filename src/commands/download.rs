@@ -1,16 +1,30 @@
-use crate::args::WriteMethod;
+use crate::args::{ProgressMode, WriteMethod};
 use crate::{
-    args::DownloadArgs,
+    args::{BatchArgs, DownloadArgs},
     fmt,
-    progress::Painter as ProgressPainter,
+    model::{downloading::DownloadOptions, history::HistoryEntry},
+    progress::{Painter as ProgressPainter, ProgressEvent, Tui, TuiCommand},
+    puller::{
+        limit::{LimitedPuller, RateLimiter},
+        mirror::MirrorPuller,
+    },
+    pusher::WriteTracker,
     store::Store,
-    utils::{confirm::confirm, sanitize::sanitize, space::check_free_space},
+    utils::{
+        checksum::{Checksum, ChecksumAlgo, encode_hex, find_checksum},
+        confirm::confirm,
+        fingerprint::{fingerprint_file, verify_remote},
+        sanitize::sanitize,
+        schedule::{Schedule, now_seconds},
+        space::check_free_space,
+        thread_budget::{BudgetPermit, ThreadBudget},
+    },
 };
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{ContextCompat, Result, WrapErr, bail};
 use dialoguer::{MultiSelect, theme::ColorfulTheme};
 use fast_down::file::MmapFilePusher;
 use fast_down::{
-    BoxPusher, Event, Merge, ProgressEntry, Proxy, Total,
+    BoxPusher, Event, Merge, ProgressEntry, Proxy, Total, UrlInfo,
     fast_puller::{FastDownPuller, FastDownPullerOptions, build_client},
     file::FilePusher,
     getifaddrs::get_available_local_ips,
@@ -21,23 +35,570 @@ use fast_down::{
     unique_path::gen_unique_path,
 };
 use file_alloc::FileAlloc;
+use futures::StreamExt;
 use parking_lot::Mutex;
-use reqwest::header;
+use reqwest::{Client, Response, header};
 use std::{
+    collections::HashMap,
     net::IpAddr,
+    path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
 };
-use tokio::fs::{self, OpenOptions};
+use tokio::{
+    fs::{self, OpenOptions},
+    sync::mpsc::{self, UnboundedReceiver},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
 use url::Url;
 
 #[inline]
-fn cancel_expected() -> Result<()> {
-    eprintln!("{}", t!("msg.cancel"));
+fn cancel_expected<T: Default>(ctx: &DownloadContext) -> Result<T> {
+    ctx.eprintln(&t!("msg.cancel"))?;
+    Ok(T::default())
+}
+
+/// 弱 ETag 下载时定期保存指纹的间隔, 避免崩溃或断电后只能重新下载
+const FINGERPRINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 一次调用中各下载任务共享的状态
+#[derive(Debug, Clone)]
+struct DownloadContext {
+    /// 当前任务使用的状态数据库
+    store: Store,
+    /// 所有任务的状态数据库, 批量下载时可能因 `dir=` 而各不相同
+    stores: Vec<Store>,
+    cancel: CancellationToken,
+    /// 批量下载时共享的进度条, 为 None 时每个任务单独绘制
+    painter: Option<Arc<Mutex<ProgressPainter>>>,
+    /// 批量下载时共享的线程预算
+    budget: Option<ThreadBudget>,
+    /// 所有任务共享的限速器
+    limiter: RateLimiter,
+    /// 所有任务的写入状态, 暂停时用于等待已下载的数据写入磁盘
+    writes: WriteTracker,
+}
+
+impl DownloadContext {
+    /// 批量下载时经由共享的进度条输出, 避免打乱进度条
+    fn println(&self, msg: &str) -> Result<()> {
+        match &self.painter {
+            Some(painter) => painter.lock().print(msg)?,
+            None => println!("{msg}"),
+        }
+        Ok(())
+    }
+
+    fn eprintln(&self, msg: &str) -> Result<()> {
+        match &self.painter {
+            Some(painter) => painter.lock().print(msg)?,
+            None => eprintln!("{msg}"),
+        }
+        Ok(())
+    }
+
+    /// 全屏界面中先暂时退出, 使提示显示在终端中并能读取输入
+    async fn confirm(&self, yes: bool, prompt: &str, default: bool) -> Result<bool> {
+        if let Some(painter) = &self.painter {
+            painter.lock().suspend_tui()?;
+        }
+        let res = confirm(yes, prompt, default).await;
+        if let Some(painter) = &self.painter {
+            painter.lock().resume_tui();
+        }
+        Ok(res?)
+    }
+}
+
+/// 运行时调整限速: 收到 SIGUSR1 时减半, 收到 SIGUSR2 时加倍, 并按时间表切换
+fn spawn_rate_control(ctx: DownloadContext, schedule: Option<Schedule>) -> Result<JoinHandle<()>> {
+    #[cfg(unix)]
+    let (mut slower, mut faster) = {
+        use tokio::signal::unix::{SignalKind, signal};
+        (
+            signal(SignalKind::user_defined1())?,
+            signal(SignalKind::user_defined2())?,
+        )
+    };
+    Ok(tokio::spawn(async move {
+        let limiter = &ctx.limiter;
+        loop {
+            #[cfg(unix)]
+            let signaled = async {
+                tokio::select! {
+                    _ = slower.recv() => match limiter.rate() {
+                        // 未限速时以当前速度为基准
+                        0 => limiter.speed() as u64 / 2,
+                        rate => rate / 2,
+                    }
+                    .max(1024),
+                    _ = faster.recv() => limiter.rate().saturating_mul(2),
+                }
+            };
+            #[cfg(not(unix))]
+            let signaled = std::future::pending::<u64>();
+            let scheduled = async {
+                match &schedule {
+                    Some(schedule) => {
+                        tokio::time::sleep(schedule.next_change(now_seconds())).await;
+                        schedule.rate_at(now_seconds())
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            let rate = tokio::select! {
+                rate = signaled => rate,
+                rate = scheduled => rate,
+            };
+            if rate != limiter.rate() {
+                limiter.set_rate(rate);
+                let _ = print_rate(&ctx, rate);
+            }
+        }
+    }))
+}
+
+/// 暂停时停止所有线程, 并在已下载的数据写入后保存进度; 继续时在同一进程中恢复下载
+async fn set_paused(ctx: &DownloadContext, paused: bool) -> Result<()> {
+    if !ctx.limiter.set_paused(paused) {
+        return Ok(());
+    }
+    if !paused {
+        return ctx.println(&t!("msg.resumed"));
+    }
+    ctx.writes.flush().await?;
+    for store in &ctx.stores {
+        store.force_flush()?;
+    }
+    ctx.println(&t!("msg.paused"))
+}
+
+/// 全屏界面中默认开启, 其他模式下默认保留 shell 的作业控制, Ctrl-Z 仍然挂起进程
+fn pause_on_tstp(args: &DownloadArgs) -> bool {
+    args.pause_on_tstp || args.progress == ProgressMode::Tui
+}
+
+/// 收到 SIGTSTP (Ctrl-Z) 时暂停下载而不是挂起进程, 收到 SIGCONT 时继续
+fn spawn_pause_control(ctx: DownloadContext) -> Result<JoinHandle<()>> {
+    #[cfg(unix)]
+    let (mut pause, mut resume) = {
+        use tokio::signal::unix::{SignalKind, signal};
+        (
+            signal(SignalKind::from_raw(libc::SIGTSTP))?,
+            signal(SignalKind::from_raw(libc::SIGCONT))?,
+        )
+    };
+    Ok(tokio::spawn(async move {
+        loop {
+            #[cfg(unix)]
+            let paused = tokio::select! {
+                _ = pause.recv() => true,
+                _ = resume.recv() => false,
+            };
+            #[cfg(not(unix))]
+            let paused = std::future::pending::<bool>().await;
+            if let Err(err) = set_paused(&ctx, paused).await {
+                let _ = ctx.eprintln(&format!("{}: {err:#}", t!("err.pause")));
+            }
+        }
+    }))
+}
+
+async fn stop_rate_control(handle: JoinHandle<()>) {
+    handle.abort();
+    let _ = handle.await;
+}
+
+fn print_rate(ctx: &DownloadContext, rate: u64) -> Result<()> {
+    match rate {
+        0 => ctx.println(&t!("msg.no-limit-rate")),
+        rate => ctx.println(&t!("msg.limit-rate", rate = fmt::format_size(rate as f64))),
+    }
+}
+
+/// 有时间表时按当前时间取限速, 否则使用 --limit-rate
+fn initial_rate(args: &DownloadArgs) -> u64 {
+    args.schedule
+        .as_ref()
+        .map_or(args.limit_rate, |schedule| schedule.rate_at(now_seconds()))
+}
+
+fn locate_store(args: &DownloadArgs) -> PathBuf {
+    Store::locate(
+        args.state_db.as_deref(),
+        &args.save_folder,
+        args.project_state,
+    )
+}
+
+type SharedPainter = (
+    Arc<Mutex<ProgressPainter>>,
+    Option<JoinHandle<()>>,
+    Option<UnboundedReceiver<TuiCommand>>,
+);
+
+/// 创建多个任务共享的进度条, 全屏界面时同时返回按键的接收端
+fn shared_painter(
+    args: &DownloadArgs,
+    threads: usize,
+    limiter: &RateLimiter,
+) -> Result<SharedPainter> {
+    let mut painter =
+        ProgressPainter::new(args.progress_width, 0.9, args.repaint_gap, args.progress)?;
+    painter.set_limiter(limiter.clone());
+    painter.schedule = args.schedule.clone();
+    painter.plain_interval = args.plain_interval;
+    painter.plain_step = args.plain_step;
+    let commands = (args.progress == ProgressMode::Tui).then(|| {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut tui = Tui::new(tx);
+        tui.threads = Some(threads);
+        painter.tui = Some(tui);
+        rx
+    });
+    let enabled = painter.enabled();
+    let painter = Arc::new(Mutex::new(painter));
+    let painter_handle = enabled.then(|| ProgressPainter::start_update_thread(painter.clone()));
+    Ok((painter, painter_handle, commands))
+}
+
+/// 处理全屏界面中的按键
+fn spawn_tui_control(
+    ctx: DownloadContext,
+    mut commands: UnboundedReceiver<TuiCommand>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(command) = commands.recv().await {
+            match command {
+                TuiCommand::TogglePause => {
+                    if let Err(err) = set_paused(&ctx, !ctx.limiter.is_paused()).await {
+                        let _ = ctx.eprintln(&format!("{}: {err:#}", t!("err.pause")));
+                    }
+                }
+                TuiCommand::AddThread | TuiCommand::RemoveThread => {
+                    let delta = if command == TuiCommand::AddThread {
+                        1
+                    } else {
+                        -1
+                    };
+                    if let Some(budget) = &ctx.budget {
+                        let threads = budget.resize(delta);
+                        if let Some(painter) = &ctx.painter
+                            && let Some(tui) = &mut painter.lock().tui
+                        {
+                            tui.threads = Some(threads);
+                        }
+                    }
+                }
+                TuiCommand::Quit => ctx.cancel.cancel(),
+            }
+        }
+    })
+}
+
+/// 停止重绘并退出全屏界面
+fn stop_painter(painter: &Mutex<ProgressPainter>, handle: Option<JoinHandle<()>>) -> Result<()> {
+    if let Some(handle) = handle {
+        handle.abort();
+    }
+    painter.lock().close_tui()?;
     Ok(())
 }
 
-pub async fn download(mut args: DownloadArgs) -> Result<()> {
+pub async fn download(args: DownloadArgs) -> Result<()> {
+    let limiter = RateLimiter::new(initial_rate(&args));
+    // 全屏界面使用共享的进度条, 并通过线程预算调整线程数;
+    // JSON 模式也需要在下载开始前创建, 使所有消息都输出为 JSON
+    let (painter, painter_handle, commands) =
+        if matches!(args.progress, ProgressMode::Tui | ProgressMode::Json) {
+            let (painter, painter_handle, commands) =
+                shared_painter(&args, args.threads, &limiter)?;
+            (Some(painter), painter_handle, commands)
+        } else {
+            (None, None, None)
+        };
+    let (store, warnings) = Store::open(locate_store(&args)).await?;
+    let store = store.with_sidecar(args.sidecar_state);
+    let ctx = DownloadContext {
+        stores: vec![store.clone()],
+        store,
+        cancel: CancellationToken::new(),
+        budget: (args.progress == ProgressMode::Tui).then(|| ThreadBudget::new(args.threads)),
+        painter,
+        limiter,
+        writes: WriteTracker::default(),
+    };
+    for warning in warnings {
+        ctx.eprintln(&warning)?;
+    }
+    if ctx.limiter.rate() > 0 {
+        print_rate(&ctx, ctx.limiter.rate())?;
+    }
+    let rate_control = spawn_rate_control(ctx.clone(), args.schedule.clone())?;
+    let pause_control = pause_on_tstp(&args)
+        .then(|| spawn_pause_control(ctx.clone()))
+        .transpose()?;
+    let tui_control = commands.map(|commands| spawn_tui_control(ctx.clone(), commands));
+    let res = download_file(args, &ctx).await;
+    stop_rate_control(rate_control).await;
+    if let Some(pause_control) = pause_control {
+        pause_control.abort();
+    }
+    if let Some(tui_control) = tui_control {
+        tui_control.abort();
+    }
+    if let Some(painter) = &ctx.painter {
+        stop_painter(painter, painter_handle)?;
+    }
+    res
+}
+
+/// 限速, 时间表与 sidecar 等选项只能在命令行中指定, 取第一个任务的即可;
+/// 输入文件中的 `dir=` 会改变项目内数据库的位置, 因此每个任务使用各自位置的数据库
+pub async fn download_batch(args: BatchArgs) -> Result<()> {
+    let total = args.tasks.len();
+    if args.tasks.is_empty() {
+        return Ok(());
+    }
+    let mut stores: HashMap<PathBuf, Store> = HashMap::new();
+    let mut warnings = Vec::new();
+    let mut tasks = Vec::with_capacity(total);
+    for task in args.tasks {
+        let db_path = locate_store(&task);
+        let store = match stores.get(&db_path) {
+            Some(store) => store.clone(),
+            None => {
+                let (store, store_warnings) = Store::open(db_path.clone()).await?;
+                let store = store.with_sidecar(task.sidecar_state);
+                warnings.extend(store_warnings);
+                stores.insert(db_path, store.clone());
+                store
+            }
+        };
+        tasks.push((task, store));
+    }
+    let first = &tasks[0].0;
+    let limiter = RateLimiter::new(initial_rate(first));
+    let (painter, painter_handle, commands) = shared_painter(first, args.threads, &limiter)?;
+    let ctx = DownloadContext {
+        store: tasks[0].1.clone(),
+        stores: stores.into_values().collect(),
+        cancel: CancellationToken::new(),
+        painter: Some(painter.clone()),
+        budget: Some(ThreadBudget::new(args.threads)),
+        limiter,
+        writes: WriteTracker::default(),
+    };
+    for warning in warnings {
+        ctx.eprintln(&warning)?;
+    }
+    if ctx.limiter.rate() > 0 {
+        print_rate(&ctx, ctx.limiter.rate())?;
+    }
+    let rate_control = spawn_rate_control(ctx.clone(), first.schedule.clone())?;
+    let pause_control = pause_on_tstp(first)
+        .then(|| spawn_pause_control(ctx.clone()))
+        .transpose()?;
+    let tui_control = commands.map(|commands| spawn_tui_control(ctx.clone(), commands));
+    // Some(true): 下载完成, Some(false): 下载失败, None: 已取消
+    let outcomes: Vec<Option<bool>> = futures::stream::iter(tasks)
+        .map(|(task, store)| {
+            let ctx = DownloadContext {
+                store,
+                ..ctx.clone()
+            };
+            async move {
+                if ctx.cancel.is_cancelled() {
+                    return None;
+                }
+                let url = task.url.clone();
+                match download_file(task, &ctx).await {
+                    Ok(()) if ctx.cancel.is_cancelled() => None,
+                    Ok(()) => Some(true),
+                    Err(err) => {
+                        let _ = ctx.println(&format!("{}: {url}\n{err:#}", t!("err.download")));
+                        Some(false)
+                    }
+                }
+            }
+        })
+        .buffer_unordered(args.jobs)
+        .collect()
+        .await;
+    stop_rate_control(rate_control).await;
+    if let Some(pause_control) = pause_control {
+        pause_control.abort();
+    }
+    if let Some(tui_control) = tui_control {
+        tui_control.abort();
+    }
+    stop_painter(&painter, painter_handle)?;
+    drop(ctx);
+    let succeeded = outcomes.iter().filter(|o| **o == Some(true)).count();
+    let failed = outcomes.iter().filter(|o| **o == Some(false)).count();
+    let summary = t!(
+        "msg.batch-summary",
+        succeeded = succeeded,
+        failed = failed,
+        total = total
+    );
+    let mut painter = painter.lock();
+    if painter.mode == ProgressMode::Json {
+        painter.print(&summary)?;
+    } else {
+        println!("{summary}");
+    }
+    drop(painter);
+    if failed > 0 {
+        bail!("{}", t!("err.batch-failed", count = failed));
+    }
+    Ok(())
+}
+
+/// 没有 ETag 或为弱 ETag 时无法保证续传的正确性
+fn is_weak_etag(etag: Option<&str>) -> bool {
+    etag.is_none_or(|etag| etag.starts_with("W/"))
+}
+
+/// 预取所有镜像, 只保留与主链接大小和 ETag 一致且支持分片下载的镜像
+async fn prefetch_mirrors(
+    client: &Client,
+    mirrors: &[String],
+    info: &UrlInfo,
+    ctx: &DownloadContext,
+) -> Result<Vec<(UrlInfo, Response)>> {
+    let results = futures::future::join_all(mirrors.iter().map(|mirror| async move {
+        let url = Url::parse(mirror).map_err(|err| format!("{err:?}"))?;
+        client
+            .prefetch(url)
+            .await
+            .map_err(|(err, _)| format!("{err:?}"))
+    }))
+    .await;
+    let mut accepted = Vec::with_capacity(mirrors.len());
+    for (mirror, result) in mirrors.iter().zip(results) {
+        match result {
+            Ok((mirror_info, resp))
+                if mirror_info.fast_download
+                    && mirror_info.size == info.size
+                    && mirror_info.file_id.etag == info.file_id.etag =>
+            {
+                accepted.push((mirror_info, resp))
+            }
+            Ok((mirror_info, _)) => ctx.eprintln(&t!(
+                "msg.mirror-mismatch",
+                url = mirror,
+                size = mirror_info.size,
+                etag = mirror_info.file_id.etag : {:?}
+            ))?,
+            Err(err) => ctx.eprintln(&format!(
+                "{}: {mirror}\n{err}",
+                t!("err.mirror-unavailable")
+            ))?,
+        }
+    }
+    Ok(accepted)
+}
+
+/// 下载校验文件并找到对应的校验值
+///
+/// 指定 `--checksum-url` 时找不到校验值会报错, `--verify-sidecar` 找不到时只给出警告
+async fn fetch_sidecar_checksum(
+    client: &Client,
+    args: &DownloadArgs,
+    info: &UrlInfo,
+    ctx: &DownloadContext,
+) -> Result<Option<Checksum>> {
+    let checksum = if let Some(checksum_url) = &args.checksum_url {
+        let url = Url::parse(checksum_url)?;
+        fetch_checksum(client, url.clone(), &info.raw_name)
+            .await?
+            .with_context(|| {
+                t!(
+                    "err.checksum-not-found",
+                    url = checksum_url,
+                    name = info.raw_name
+                )
+                .to_string()
+            })
+            .map(|checksum| (url, checksum))?
+    } else if args.verify_sidecar {
+        let mut sidecar = info.final_url.clone();
+        sidecar.set_path(&format!("{}.sha256", info.final_url.path()));
+        let mut found = None;
+        for url in [sidecar, info.final_url.join("SHA256SUMS")?] {
+            if let Ok(Some(checksum)) = fetch_checksum(client, url.clone(), &info.raw_name).await {
+                found = Some((url, checksum));
+                break;
+            }
+        }
+        match found {
+            Some(found) => found,
+            None => {
+                ctx.eprintln(&t!("msg.sidecar-not-found"))?;
+                return Ok(None);
+            }
+        }
+    } else {
+        return Ok(None);
+    };
+    let (url, checksum) = checksum;
+    ctx.println(&t!("msg.checksum-found", url = url, checksum = checksum))?;
+    Ok(Some(checksum))
+}
+
+async fn fetch_checksum(client: &Client, url: Url, file_name: &str) -> Result<Option<Checksum>> {
+    let hint = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(ChecksumAlgo::from_file_name);
+    let content = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(find_checksum(&content, file_name, hint))
+}
+
+/// 下载单个文件, 完成或失败时记录到历史中, 取消的下载不记录
+async fn download_file(args: DownloadArgs, ctx: &DownloadContext) -> Result<()> {
+    let mut history = HistoryEntry {
+        url: args.url.clone(),
+        path: args.save_folder.to_string_lossy().to_string(),
+        size: 0,
+        elapsed: Duration::ZERO,
+        threads: args.threads,
+        retries: 0,
+        finished_at: 0,
+        error: None,
+    };
+    let res = try_download_file(args, ctx, &mut history).await;
+    let completed = match &res {
+        Ok(completed) => *completed,
+        Err(err) => {
+            history.error = Some(format!("{err:#}"));
+            true
+        }
+    };
+    if completed {
+        history.finished_at = chrono::Utc::now().timestamp();
+        // 历史记录写入失败不影响下载结果
+        if let Err(err) = ctx.store.add_history(&history) {
+            let _ = ctx.eprintln(&format!("{}: {err:#}", t!("err.database-write")));
+        }
+    }
+    res.map(|_| ())
+}
+
+/// 返回下载是否已完成, 被取消时为 false
+async fn try_download_file(
+    mut args: DownloadArgs,
+    ctx: &DownloadContext,
+    history: &mut HistoryEntry,
+) -> Result<bool> {
     let url = Url::parse(&args.url)?;
     if args.browser {
         args.headers
@@ -51,7 +612,8 @@ pub async fn download(mut args: DownloadArgs) -> Result<()> {
             .or_insert("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/144.0.0.0 Safari/537.36 Edg/144.0.0.0".parse()?);
     }
     if args.verbose {
-        dbg!(&args);
+        // 经由进度条输出, JSON 模式下同样为一行 JSON
+        ctx.eprintln(&format!("{args:#?}"))?;
     }
     let proxy = match args.proxy.as_deref() {
         Some("") => Proxy::No,
@@ -65,18 +627,39 @@ pub async fn download(mut args: DownloadArgs) -> Result<()> {
         args.accept_invalid_hostnames,
         None,
     )?;
-    let store = Store::new().await?;
     let (info, resp) = loop {
         match client.prefetch(url.clone()).await {
             Ok(info) => break info,
             Err((err, retry_gap)) => {
-                eprintln!("{}: {:#?}", t!("err.url-info"), err);
-                tokio::time::sleep(retry_gap.unwrap_or(args.retry_gap)).await;
+                ctx.eprintln(&format!("{}: {:#?}", t!("err.url-info"), err))?;
+                tokio::select! {
+                    _ = ctx.cancel.cancelled() => return cancel_expected(ctx),
+                    _ = tokio::time::sleep(retry_gap.unwrap_or(args.retry_gap)) => {}
+                }
             }
         }
     };
+    let mirrors = if info.fast_download {
+        prefetch_mirrors(&client, &args.mirrors, &info, ctx).await?
+    } else {
+        Vec::new()
+    };
+    if !args.mirrors.is_empty() {
+        ctx.println(&t!("msg.mirrors", count = mirrors.len() + 1))?;
+    }
+    if args.checksum.is_none() {
+        args.checksum = fetch_sidecar_checksum(&client, &args, &info, ctx).await?;
+    }
+    let permit = ctx
+        .budget
+        .as_ref()
+        .filter(|_| info.fast_download)
+        .map(ThreadBudget::acquire);
     let threads = if info.fast_download {
-        args.threads.max(1)
+        permit
+            .as_ref()
+            .map_or(args.threads, BudgetPermit::threads)
+            .max(1)
     } else {
         1
     };
@@ -85,107 +668,141 @@ pub async fn download(mut args: DownloadArgs) -> Result<()> {
         args.file_name.as_ref().unwrap_or(&info.raw_name)
     ));
     let save_path = soft_canonicalize::soft_canonicalize(args.save_folder.join(&filename))?;
-    println!(
-        "{}",
-        fmt::format_download_info(&info, &filename, &save_path, threads)
-    );
+    history.path = save_path.to_string_lossy().to_string();
+    history.size = info.size;
+    history.threads = threads;
+    ctx.println(&fmt::format_download_info(
+        &info, &filename, &save_path, threads,
+    ))?;
     #[allow(clippy::single_range_in_vec_init)]
     let mut download_chunks = vec![0..info.size];
     let mut resume_download = false;
+    // 指纹不匹配时已确定需要重新下载, 无需再次确认覆盖
+    let mut fingerprint_restart = false;
     let mut write_progress: Vec<ProgressEntry> = Vec::with_capacity(threads);
     let mut elapsed = 0;
 
     if fs::try_exists(&save_path).await? {
         if args.resume
             && info.fast_download
-            && let Some(entry) = store.get_entry(&save_path)
+            && let Some(entry) = ctx.store.get_entry(&save_path)
         {
-            let downloaded: u64 = entry.progress.iter().map(|(a, b)| b - a).sum();
+            let downloaded = entry.downloaded();
             if downloaded < info.size {
                 write_progress.extend(entry.progress.iter().map(|(a, b)| *a..*b));
                 download_chunks =
                     invert(write_progress.iter().cloned(), info.size, args.chunk_window).collect();
                 resume_download = true;
                 elapsed = entry.elapsed.as_millis() as u64;
-                println!("{}", t!("msg.resume-download"));
-                println!(
-                    "{}",
-                    t!(
-                        "msg.download",
-                        completed = fmt::format_size(downloaded as f64),
-                        total = fmt::format_size(info.size as f64),
-                        percentage = downloaded * 100 / info.size
-                    ),
-                );
+                ctx.println(&t!("msg.resume-download"))?;
+                ctx.println(&t!(
+                    "msg.download",
+                    completed = fmt::format_size(downloaded as f64),
+                    total = fmt::format_size(info.size as f64),
+                    percentage = downloaded * 100 / info.size
+                ))?;
                 if entry.file_size != info.size
-                    && !confirm(
-                        args.yes,
-                        &t!(
-                            "msg.size-mismatch",
-                            saved_size = entry.file_size,
-                            new_size = info.size
-                        ),
-                        false,
-                    )
-                    .await?
+                    && !ctx
+                        .confirm(
+                            args.yes,
+                            &t!(
+                                "msg.size-mismatch",
+                                saved_size = entry.file_size,
+                                new_size = info.size
+                            ),
+                            false,
+                        )
+                        .await?
                 {
-                    return cancel_expected();
+                    return cancel_expected(ctx);
                 }
-                if entry.etag.as_deref() != info.file_id.etag.as_deref() {
-                    if !confirm(
-                        args.yes,
-                        &t!(
-                            "msg.etag-mismatch",
-                            saved_etag = entry.etag : {:?},
-                            new_etag = info.file_id.etag : {:?}
-                        ),
-                        false,
-                    )
-                    .await?
-                    {
-                        return cancel_expected();
-                    }
-                } else if let Some(ref etag) = entry.etag
-                    && etag.starts_with("W/")
+                let fingerprint = if entry.etag.as_deref() == info.file_id.etag.as_deref()
+                    && is_weak_etag(entry.etag.as_deref())
+                    && !entry.fingerprint.is_empty()
                 {
-                    if !confirm(args.yes, &t!("msg.weak-etag", etag = etag), false).await? {
-                        return cancel_expected();
+                    verify_remote(&client, &info.final_url, &entry.fingerprint)
+                        .await
+                        .ok()
+                } else {
+                    None
+                };
+                match fingerprint {
+                    Some(true) => ctx.println(&t!("msg.fingerprint-match"))?,
+                    Some(false) => {
+                        ctx.println(&t!("msg.fingerprint-mismatch"))?;
+                        write_progress.clear();
+                        download_chunks.clear();
+                        download_chunks.push(0..info.size);
+                        resume_download = false;
+                        fingerprint_restart = true;
+                        elapsed = 0;
+                    }
+                    None => {
+                        if entry.etag.as_deref() != info.file_id.etag.as_deref() {
+                            if !ctx
+                                .confirm(
+                                    args.yes,
+                                    &t!(
+                                        "msg.etag-mismatch",
+                                        saved_etag = entry.etag : {:?},
+                                        new_etag = info.file_id.etag : {:?}
+                                    ),
+                                    false,
+                                )
+                                .await?
+                            {
+                                return cancel_expected(ctx);
+                            }
+                        } else if let Some(ref etag) = entry.etag
+                            && etag.starts_with("W/")
+                        {
+                            if !ctx
+                                .confirm(args.yes, &t!("msg.weak-etag", etag = etag), false)
+                                .await?
+                            {
+                                return cancel_expected(ctx);
+                            }
+                        } else if entry.etag.is_none()
+                            && !ctx.confirm(args.yes, &t!("msg.no-etag"), false).await?
+                        {
+                            return cancel_expected(ctx);
+                        }
+                        if entry.last_modified.as_deref() != info.file_id.last_modified.as_deref()
+                            && !ctx
+                                .confirm(
+                                    args.yes,
+                                    &t!(
+                                        "msg.last-modified-mismatch",
+                                        saved_last_modified = entry.last_modified : {:?},
+                                        new_last_modified = info.file_id.last_modified : {:?}
+                                    ),
+                                    false,
+                                )
+                                .await?
+                        {
+                            return cancel_expected(ctx);
+                        }
                     }
-                } else if entry.etag.is_none()
-                    && !confirm(args.yes, &t!("msg.no-etag"), false).await?
-                {
-                    return cancel_expected();
-                }
-                if entry.last_modified.as_deref() != info.file_id.last_modified.as_deref()
-                    && !confirm(
-                        args.yes,
-                        &t!(
-                            "msg.last-modified-mismatch",
-                            saved_last_modified = entry.last_modified : {:?},
-                            new_last_modified = info.file_id.last_modified : {:?}
-                        ),
-                        false,
-                    )
-                    .await?
-                {
-                    return cancel_expected();
                 }
             }
         }
         if !args.yes
             && !resume_download
             && !args.force
-            && !confirm(args.yes, &t!("msg.file-overwrite"), false).await?
+            && !fingerprint_restart
+            && !ctx
+                .confirm(args.yes, &t!("msg.file-overwrite"), false)
+                .await?
         {
-            return cancel_expected();
+            return cancel_expected(ctx);
         }
     }
     if let Some(size) = check_free_space(&save_path, download_chunks.total())? {
-        eprintln!(
-            "{}",
-            t!("msg.lack-of-space", size = fmt::format_size(size as f64)),
-        );
-        return cancel_expected();
+        ctx.eprintln(&t!(
+            "msg.lack-of-space",
+            size = fmt::format_size(size as f64)
+        ))?;
+        return cancel_expected(ctx);
     }
 
     let available_ips: Arc<[IpAddr]> = if args.ips.is_empty() && args.interface {
@@ -206,32 +823,43 @@ pub async fn download(mut args: DownloadArgs) -> Result<()> {
                 Arc::from(picked)
             }
             Err(e) => {
-                eprintln!("{}: {:?}", t!("err.get-ips"), e);
+                ctx.eprintln(&format!("{}: {:?}", t!("err.get-ips"), e))?;
                 Arc::from([])
             }
         }
     } else {
-        args.ips.iter().flat_map(|s| s.parse()).collect()
+        args.ips
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<_, _>>()
+            .with_context(|| t!("err.invalid-ip", ips = args.ips.join(", ")))?
     };
-    println!(
+    ctx.println(&format!(
         "{}: {:?}",
         t!("msg.available-ips"),
         available_ips
             .iter()
             .map(|a| a.to_string())
             .collect::<Vec<_>>()
-    );
+    ))?;
 
-    let puller = FastDownPuller::new(FastDownPullerOptions {
-        url: info.final_url,
-        headers: Arc::new(args.headers),
-        proxy,
-        accept_invalid_certs: args.accept_invalid_certs,
-        accept_invalid_hostnames: args.accept_invalid_hostnames,
-        file_id: info.file_id.clone(),
-        resp: Some(Arc::new(Mutex::new(Some(resp)))),
-        available_ips,
-    })?;
+    let mut options = DownloadOptions::from_args(&args);
+    options.ips = available_ips.iter().map(|ip| ip.to_string()).collect();
+    let headers = Arc::new(args.headers);
+    let mut pullers = Vec::with_capacity(mirrors.len() + 1);
+    for (info, resp) in [(info.clone(), resp)].into_iter().chain(mirrors) {
+        pullers.push(FastDownPuller::new(FastDownPullerOptions {
+            url: info.final_url,
+            headers: headers.clone(),
+            proxy,
+            accept_invalid_certs: args.accept_invalid_certs,
+            accept_invalid_hostnames: args.accept_invalid_hostnames,
+            file_id: info.file_id,
+            resp: Some(Arc::new(Mutex::new(Some(resp)))),
+            available_ips: available_ips.clone(),
+        })?);
+    }
+    let puller = LimitedPuller::new(MirrorPuller::new(pullers), ctx.limiter.clone());
     if let Some(parent) = save_path.parent()
         && let Err(err) = fs::create_dir_all(parent).await
         && err.kind() != std::io::ErrorKind::AlreadyExists
@@ -246,18 +874,20 @@ pub async fn download(mut args: DownloadArgs) -> Result<()> {
         .open(&save_path)
         .await?;
     if info.size > 0 && args.pre_alloc {
-        println!("{}", t!("msg.file-allocating"));
+        ctx.println(&t!("msg.file-allocating"))?;
         file.allocate(info.size).await?;
-        println!("{}", t!("msg.file-allocated"));
+        ctx.println(&t!("msg.file-allocated"))?;
     }
-    let pusher = if info.fast_download
-        && cfg!(target_pointer_width = "64")
-        && matches!(args.write_method, WriteMethod::Mmap)
-    {
-        BoxPusher::new(MmapFilePusher::new(file, info.size).await?)
-    } else {
-        BoxPusher::new(FilePusher::new(file, info.size, args.write_buffer_size).await?)
-    };
+    let pusher = ctx.writes.pusher(
+        if info.fast_download
+            && cfg!(target_pointer_width = "64")
+            && matches!(args.write_method, WriteMethod::Mmap)
+        {
+            BoxPusher::new(MmapFilePusher::new(file, info.size).await?)
+        } else {
+            BoxPusher::new(FilePusher::new(file, info.size, args.write_buffer_size).await?)
+        },
+    );
     let result = if info.fast_download {
         download_multi(
             puller,
@@ -283,108 +913,228 @@ pub async fn download(mut args: DownloadArgs) -> Result<()> {
         )
     };
 
+    let finished = CancellationToken::new();
+    let _finished_guard = finished.clone().drop_guard();
+    if let Some(mut permit) = permit {
+        let result = result.clone();
+        let cancel = ctx.cancel.clone();
+        let finished = finished.clone();
+        let min_chunk_size = args.min_chunk_size;
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = finished.cancelled() => break,
+                    threads = permit.changed() => {
+                        if cancel.is_cancelled() {
+                            break;
+                        }
+                        result.set_threads(threads, min_chunk_size);
+                    }
+                }
+            }
+        });
+    }
     let result_clone = result.clone();
+    let cancel = ctx.cancel.clone();
+    let finished_clone = finished.clone();
     tokio::spawn(async move {
-        tokio::signal::ctrl_c().await.unwrap();
+        let finished = finished_clone;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => {
+                res.unwrap();
+                cancel.cancel();
+            }
+            _ = cancel.cancelled() => {}
+            _ = finished.cancelled() => return,
+        }
         result_clone.abort();
     });
     if !resume_download {
-        store.init_entry(&save_path, filename, info.size, &info.file_id, url)?;
+        ctx.store.init_entry(
+            &save_path,
+            filename.clone(),
+            info.size,
+            &info.file_id,
+            url,
+            options,
+        )?;
     }
 
+    let weak_etag = info.fast_download && is_weak_etag(info.file_id.etag.as_deref());
+    let fingerprint_handle = weak_etag.then(|| {
+        let store = ctx.store.clone();
+        let save_path = save_path.clone();
+        let finished = finished.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = finished.cancelled() => return,
+                    _ = tokio::time::sleep(FINGERPRINT_INTERVAL) => {}
+                }
+                let Some(entry) = store.get_entry(&save_path) else {
+                    continue;
+                };
+                if let Ok(fingerprint) = fingerprint_file(save_path.clone(), entry.progress).await {
+                    store.update_fingerprint(&save_path, fingerprint);
+                }
+            }
+        })
+    });
+
     let start = Instant::now() - Duration::from_millis(elapsed);
-    let painter = Arc::new(Mutex::new(ProgressPainter::new(
-        write_progress.clone(),
-        info.size,
-        args.progress_width,
-        0.9,
-        args.repaint_gap,
-        start,
-    )?));
-    let painter_handle = ProgressPainter::start_update_thread(painter.clone());
+    // 暂停的时间不计入耗时
+    let paused_base = ctx.limiter.paused_time();
+    let elapsed = || {
+        start
+            .elapsed()
+            .saturating_sub(ctx.limiter.paused_time() - paused_base)
+    };
+    let (painter, painter_handle, bar) = match &ctx.painter {
+        Some(painter) => {
+            let name = filename.strip_suffix(".fdpart").unwrap_or(&filename);
+            let bar = painter.lock().add_bar(
+                Some(name.to_string()),
+                write_progress.clone(),
+                info.size,
+                start,
+            );
+            (painter.clone(), None, bar)
+        }
+        None => {
+            let mut painter =
+                ProgressPainter::new(args.progress_width, 0.9, args.repaint_gap, args.progress)?;
+            painter.set_limiter(ctx.limiter.clone());
+            painter.schedule = args.schedule.clone();
+            painter.plain_interval = args.plain_interval;
+            painter.plain_step = args.plain_step;
+            let bar = painter.add_bar(None, write_progress.clone(), info.size, start);
+            let enabled = painter.enabled();
+            let painter = Arc::new(Mutex::new(painter));
+            let painter_handle =
+                enabled.then(|| ProgressPainter::start_update_thread(painter.clone()));
+            (painter, painter_handle, bar)
+        }
+    };
     let mut first_flushing = true;
+    let mut writes = ctx.writes.task();
     while let Ok(e) = result.event_chain.recv().await {
         match e {
-            Event::PullProgress(_, p) => {
+            Event::PullProgress(id, p) => {
+                writes.pulled(p.end - p.start);
                 let mut guard = painter.lock();
                 if p.start == 0 && !info.fast_download {
-                    guard.reset_progress();
+                    guard.reset_progress(bar);
                 }
-                guard.add(p);
+                guard.add(bar, id, p);
             }
             Event::PushProgress(_, p) => {
+                let len = p.end - p.start;
                 write_progress.merge_progress(p);
-                store.update_entry(
+                ctx.store.update_entry(
                     &save_path,
                     write_progress.iter().map(|r| (r.start, r.end)).collect(),
-                    start.elapsed(),
+                    elapsed(),
                 );
+                writes.pushed(len);
             }
-            Event::PullError(id, err) => painter.lock().print(&format!(
-                "{} {}\n{:?}\n",
-                t!("verbose.worker-id", id = id),
-                t!("verbose.download-error"),
-                err
-            ))?,
-            Event::PushError(_, _, err) => {
-                painter
-                    .lock()
-                    .print(&format!("{}\n{:?}\n", t!("verbose.write-error"), err))?
+            Event::PullError(id, err) => {
+                history.retries += 1;
+                let event = ProgressEvent::PullError {
+                    worker: id,
+                    error: format!("{err:?}"),
+                };
+                painter.lock().event(bar, event, args.verbose)?;
+            }
+            Event::PushError(id, _, err) => {
+                let event = ProgressEvent::PushError {
+                    worker: id,
+                    error: format!("{err:?}"),
+                };
+                painter.lock().event(bar, event, args.verbose)?;
             }
             Event::FlushError(err) => {
-                painter
-                    .lock()
-                    .print(&format!("{}\n{:?}\n", t!("verbose.write-error"), err))?
+                let event = ProgressEvent::FlushError {
+                    error: format!("{err:?}"),
+                };
+                painter.lock().event(bar, event, args.verbose)?;
             }
             Event::Pulling(id) => {
-                if args.verbose {
-                    painter.lock().print(&format!(
-                        "{} {}\n",
-                        t!("verbose.worker-id", id = id),
-                        t!("verbose.downloading")
-                    ))?;
-                }
+                let event = ProgressEvent::Pulling { worker: id };
+                painter.lock().event(bar, event, args.verbose)?;
             }
             Event::Finished(id) => {
-                if args.verbose {
-                    painter.lock().print(&format!(
-                        "{} {}\n",
-                        t!("verbose.worker-id", id = id),
-                        t!("verbose.finished")
-                    ))?;
-                }
+                let event = ProgressEvent::Finished { worker: id };
+                painter.lock().event(bar, event, args.verbose)?;
             }
+            // 暂停时连接超时断开是预期的
+            Event::PullTimeout(_) if ctx.limiter.is_paused() => {}
             Event::PullTimeout(id) => {
-                painter.lock().print(&format!(
-                    "{} {}\n",
-                    t!("verbose.worker-id", id = id),
-                    t!("verbose.pull-timeout")
-                ))?;
+                history.retries += 1;
+                let event = ProgressEvent::PullTimeout { worker: id };
+                painter.lock().event(bar, event, args.verbose)?;
             }
             Event::Pushing(_, _) => {}
             Event::Flushing => {
                 painter
                     .lock()
-                    .print(&format!("{}\n", t!("verbose.flushing")))?;
+                    .event(bar, ProgressEvent::Flushing, args.verbose)?;
                 if first_flushing {
                     first_flushing = false;
-                    store.update_entry(
+                    ctx.store.update_entry(
                         &save_path,
                         write_progress.iter().map(|r| (r.start, r.end)).collect(),
-                        start.elapsed(),
+                        elapsed(),
                     );
                 }
             }
         }
     }
-    painter.lock().update()?;
-    painter_handle.abort();
-    result.join().await?;
-    if !result.is_aborted() {
-        let output_path = gen_unique_path(save_path.with_extension("")).await?;
-        fs::rename(&save_path, &output_path).await?;
-        store.remove_entry(&save_path)?;
-        println!("{}", t!("msg.output-path", path = output_path.display()))
+    {
+        let mut guard = painter.lock();
+        if ctx.painter.is_some() {
+            guard.remove_bar(bar);
+        }
+        guard.update()?;
     }
-    Ok(())
+    if let Some(painter_handle) = painter_handle {
+        painter_handle.abort();
+    }
+    let join_result = result.join().await;
+    if let Some(handle) = fingerprint_handle {
+        handle.abort();
+    }
+    if (result.is_aborted() || join_result.is_err()) && weak_etag {
+        let progress = write_progress.iter().map(|r| (r.start, r.end)).collect();
+        if let Ok(fingerprint) = fingerprint_file(save_path.clone(), progress).await {
+            ctx.store.update_fingerprint(&save_path, fingerprint);
+        }
+    }
+    history.elapsed = elapsed();
+    join_result?;
+    if result.is_aborted() {
+        return Ok(false);
+    }
+    if let Some(checksum) = &args.checksum {
+        ctx.println(&t!("msg.checksum-verifying", algo = checksum.algo))?;
+        let digest = checksum.algo.digest_file(save_path.clone()).await?;
+        if digest != checksum.digest {
+            ctx.store.force_flush()?;
+            bail!(
+                "{}",
+                t!(
+                    "err.checksum-mismatch",
+                    expected = encode_hex(&checksum.digest),
+                    actual = encode_hex(&digest),
+                    path = save_path.display()
+                )
+            );
+        }
+        ctx.println(&t!("msg.checksum-ok"))?;
+    }
+    let output_path = gen_unique_path(save_path.with_extension("")).await?;
+    fs::rename(&save_path, &output_path).await?;
+    ctx.store.remove_entry(&save_path)?;
+    history.path = output_path.to_string_lossy().to_string();
+    ctx.println(&t!("msg.output-path", path = output_path.display()))?;
+    Ok(true)
 }
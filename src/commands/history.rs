@@ -0,0 +1,125 @@
+use crate::{
+    args::{HistoryArgs, StatsArgs},
+    fmt::{self, add_prefix_to_lines},
+    model::history::{HistoryEntry, Stats, Summary},
+    store::Store,
+};
+use color_eyre::Result;
+use std::{fmt::Write, path::Path};
+
+/// `fd stats` 中显示的最近失败条数
+const RECENT_FAILURES: usize = 5;
+
+fn format_speed(speed: f64) -> String {
+    format!("{}/s", fmt::format_size(speed))
+}
+
+#[rustfmt::skip]
+fn display_entry(entry: &HistoryEntry) -> std::result::Result<String, std::fmt::Error> {
+    let mut content = String::new();
+    writeln!(&mut content, "{}: {}", t!("db-display.url"), entry.url)?;
+    writeln!(&mut content, "{}: {}", t!("db-display.file-path"), entry.path)?;
+    writeln!(&mut content, "{}: {}", t!("db-display.size"), fmt::format_size(entry.size as f64))?;
+    writeln!(&mut content, "{}: {}", t!("db-display.elapsed"), fmt::format_time(entry.elapsed.as_secs()))?;
+    writeln!(&mut content, "{}: {}", t!("db-display.speed"), format_speed(entry.speed()))?;
+    writeln!(&mut content, "{}: {}", t!("db-display.threads"), entry.threads)?;
+    writeln!(&mut content, "{}: {}", t!("db-display.retries"), entry.retries)?;
+    if let Some(error) = &entry.error {
+        writeln!(&mut content, "{}: {}", t!("db-display.error"), error)?;
+    }
+    Ok(content.trim_end().to_string())
+}
+
+fn display_summary(summary: &Summary) -> String {
+    format!(
+        "{}, {}, {}",
+        t!(
+            "msg.stats-count",
+            succeeded = summary.succeeded,
+            failed = summary.failed
+        ),
+        fmt::format_size(summary.size as f64),
+        format_speed(summary.speed())
+    )
+}
+
+pub async fn history(args: HistoryArgs) -> Result<()> {
+    let db_path = Store::locate(args.state_db.as_deref(), Path::new("."), false);
+    let store = Store::new(db_path).await?;
+    let entries = store.history(args.limit, args.failed)?;
+    if entries.is_empty() {
+        println!("{}", t!("msg.history-empty"));
+        return Ok(());
+    }
+    let mut content = String::new();
+    writeln!(&mut content, "----------------------")?;
+    for (index, entry) in entries.iter().enumerate() {
+        writeln!(
+            &mut content,
+            "| [{}] {}: {}",
+            index + 1,
+            t!("db-display.finished-at"),
+            fmt::format_timestamp(entry.finished_at)
+        )?;
+        writeln!(
+            &mut content,
+            "{}",
+            add_prefix_to_lines(&display_entry(entry)?, "| ")
+        )?;
+        writeln!(&mut content, "----------------------")?;
+    }
+    print!("{content}");
+    Ok(())
+}
+
+pub async fn stats(args: StatsArgs) -> Result<()> {
+    let db_path = Store::locate(args.state_db.as_deref(), Path::new("."), false);
+    let store = Store::new(db_path).await?;
+    let stats = Stats::new(&store.history(None, false)?);
+    let total = &stats.total;
+    println!(
+        "{}: {}",
+        t!("db-display.downloads"),
+        t!(
+            "msg.stats-count",
+            succeeded = total.succeeded,
+            failed = total.failed
+        )
+    );
+    println!(
+        "{}: {}",
+        t!("db-display.total-size"),
+        fmt::format_size(total.size as f64)
+    );
+    println!(
+        "{}: {}",
+        t!("db-display.total-elapsed"),
+        fmt::format_time(total.elapsed.as_secs())
+    );
+    println!(
+        "{}: {}",
+        t!("db-display.speed"),
+        format_speed(total.speed())
+    );
+    if !stats.hosts.is_empty() {
+        println!("\n{}:", t!("db-display.hosts"));
+        for (host, summary) in &stats.hosts {
+            println!("  {host}: {}", display_summary(summary));
+        }
+    }
+    let failures = store.history(Some(RECENT_FAILURES), true)?;
+    if !failures.is_empty() {
+        println!("\n{}:", t!("db-display.recent-failures"));
+        for entry in &failures {
+            println!(
+                "  [{}] {}",
+                fmt::format_timestamp(entry.finished_at),
+                entry.url
+            );
+            if let Some(error) = entry.error.as_deref().and_then(|e| e.lines().next()) {
+                println!("    {error}");
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,9 +1,157 @@
-use crate::args::ListArgs;
+use crate::args::{ListArgs, ListFormat, ListSort};
+use crate::fmt::{self, escape_csv};
+use crate::model::downloading::Downloading;
+use crate::progress::render_blocks;
 use crate::store::Store;
 use color_eyre::Result;
+use serde::Serialize;
+use std::path::Path;
+use url::Url;
+
+/// 紧凑模式下进度条的宽度
+const COMPACT_BAR_WIDTH: u16 = 20;
+
+/// 供脚本读取的记录
+#[derive(Serialize)]
+struct ListRecord<'a> {
+    path: &'a str,
+    url: &'a str,
+    size: u64,
+    downloaded: u64,
+    percent: f64,
+    etag: Option<&'a str>,
+    last_modified: Option<&'a str>,
+    /// 单位: ms
+    elapsed: u64,
+}
+
+impl<'a> ListRecord<'a> {
+    fn new(path: &'a str, entry: &'a Downloading) -> Self {
+        Self {
+            path,
+            url: &entry.url,
+            size: entry.file_size,
+            downloaded: entry.downloaded(),
+            percent: (percent(entry) * 100.0).round() / 100.0,
+            etag: entry.etag.as_deref(),
+            last_modified: entry.last_modified.as_deref(),
+            elapsed: entry.elapsed.as_millis() as u64,
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        [
+            escape_csv(self.path),
+            escape_csv(self.url),
+            self.size.to_string().into(),
+            self.downloaded.to_string().into(),
+            self.percent.to_string().into(),
+            escape_csv(self.etag.unwrap_or_default()),
+            escape_csv(self.last_modified.unwrap_or_default()),
+            self.elapsed.to_string().into(),
+        ]
+        .join(",")
+    }
+}
+
+const CSV_HEADER: &str = "path,url,size,downloaded,percent,etag,last_modified,elapsed";
+
+fn percent(entry: &Downloading) -> f64 {
+    match entry.file_size {
+        0 => 0.0,
+        size => entry.downloaded() as f64 / size as f64 * 100.0,
+    }
+}
+
+/// `host=example.com` 同时匹配子域名
+fn match_host(url: &str, hosts: &[String]) -> bool {
+    let Some(host) = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+    else {
+        return false;
+    };
+    hosts.iter().any(|h| {
+        host == *h
+            || host
+                .strip_suffix(h.as_str())
+                .is_some_and(|s| s.ends_with('.'))
+    })
+}
+
+/// 按参数筛选并排序, 序号为记录在 [`Store::sorted_entries`] 中的位置加 1
+fn select(store: &Store, args: &ListArgs) -> Result<Vec<(usize, String, Downloading)>> {
+    let dir = args
+        .dir
+        .as_ref()
+        .map(soft_canonicalize::soft_canonicalize)
+        .transpose()?;
+    let mut entries: Vec<_> = store
+        .sorted_entries()
+        .into_iter()
+        .enumerate()
+        .map(|(index, (path, entry))| (index + 1, path, entry))
+        .filter(|(_, path, entry)| {
+            (args.hosts.is_empty() || match_host(&entry.url, &args.hosts))
+                && (!args.incomplete_only || entry.downloaded() < entry.file_size)
+                && dir
+                    .as_ref()
+                    .is_none_or(|dir| Path::new(path).starts_with(dir))
+        })
+        .collect();
+    match args.sort {
+        ListSort::Path => {}
+        ListSort::Size => entries.sort_by_key(|(_, _, entry)| entry.file_size),
+        ListSort::Progress => entries.sort_by(|a, b| percent(&a.2).total_cmp(&percent(&b.2))),
+        ListSort::Elapsed => entries.sort_by_key(|(_, _, entry)| entry.elapsed),
+    }
+    Ok(entries)
+}
+
+fn render_compact(index: usize, path: &str, entry: &Downloading) -> String {
+    let mut progress: Vec<_> = entry.progress.iter().map(|(a, b)| *a..*b).collect();
+    progress.sort_unstable_by_key(|r| r.start);
+    let bar = match entry.file_size {
+        0 => " ".repeat(COMPACT_BAR_WIDTH as usize),
+        size => render_blocks(&progress, size, COMPACT_BAR_WIDTH.into()),
+    };
+    format!(
+        "[{index}] |{bar}| {:>6.2}% {:>10} / {:<10} {path}",
+        percent(entry),
+        fmt::format_size(entry.downloaded() as f64),
+        fmt::format_size(entry.file_size as f64),
+    )
+}
 
 pub async fn list(args: ListArgs) -> Result<()> {
-    let store = Store::new().await?;
-    println!("{}", store.display(args.details)?);
+    let db_path = Store::locate(args.state_db.as_deref(), Path::new("."), false);
+    let store = Store::new(db_path).await?;
+    let entries = select(&store, &args)?;
+    match args.format {
+        ListFormat::Table if args.compact => {
+            for (index, path, entry) in &entries {
+                println!("{}", render_compact(*index, path, entry));
+            }
+        }
+        ListFormat::Table => println!("{}", store.display(&entries, args.details)?),
+        ListFormat::Json => {
+            let records: Vec<_> = entries
+                .iter()
+                .map(|(_, path, entry)| ListRecord::new(path, entry))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&records)?);
+        }
+        ListFormat::Ndjson => {
+            for (_, path, entry) in &entries {
+                println!("{}", serde_json::to_string(&ListRecord::new(path, entry))?);
+            }
+        }
+        ListFormat::Csv => {
+            println!("{CSV_HEADER}");
+            for (_, path, entry) in &entries {
+                println!("{}", ListRecord::new(path, entry).to_csv());
+            }
+        }
+    }
     Ok(())
 }
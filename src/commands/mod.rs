@@ -1,3 +1,7 @@
 pub mod download;
+pub mod history;
 pub mod list;
+pub mod remove;
+pub mod resume;
+pub mod state;
 pub mod update;
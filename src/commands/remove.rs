@@ -0,0 +1,39 @@
+use crate::{
+    args::RemoveArgs,
+    store::Store,
+    utils::{confirm::confirm, select::select_entries},
+};
+use color_eyre::Result;
+use std::{io::ErrorKind, path::Path};
+use tokio::fs;
+
+pub async fn remove(args: RemoveArgs) -> Result<()> {
+    let db_path = Store::locate(args.state_db.as_deref(), Path::new("."), false);
+    let store = Store::new(db_path).await?;
+    let entries = store.sorted_entries();
+    let selected = if args.all {
+        entries
+    } else {
+        select_entries(entries, &args.targets)?
+    };
+    if args.delete_files && !selected.is_empty() {
+        for (path, _) in &selected {
+            eprintln!("{path}");
+        }
+        let prompt = t!("msg.delete-files", count = selected.len());
+        if !confirm(args.yes, &prompt, false).await? {
+            return Ok(());
+        }
+    }
+    for (path, _) in &selected {
+        store.remove_entry(path)?;
+        if args.delete_files {
+            match fs::remove_file(path).await {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+    }
+    println!("{}", t!("msg.clean", count = selected.len()));
+    Ok(())
+}
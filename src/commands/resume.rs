@@ -0,0 +1,91 @@
+use crate::{
+    args::{BatchArgs, DownloadArgs, ResumeArgs},
+    commands::download,
+    model::downloading::Downloading,
+    progress::Painter,
+    store::Store,
+    utils::select::select_entries,
+};
+use color_eyre::Result;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+pub async fn resume(args: ResumeArgs) -> Result<()> {
+    let db_path = Store::locate(args.state_db.as_deref(), Path::new("."), false);
+    let (store, warnings) = Store::open(db_path.clone()).await?;
+    if !warnings.is_empty() {
+        // 按下载的进度模式输出, JSON 模式下保持每行一条 JSON
+        let mut painter = Painter::new(0, 0.9, Duration::ZERO, args.template.progress)?;
+        for warning in warnings {
+            painter.print(&warning)?;
+        }
+    }
+    let entries = store.sorted_entries();
+    drop(store);
+    let selected = match &args.target {
+        None => entries,
+        Some(target) => match select_entries(entries, std::slice::from_ref(target)) {
+            Ok(selected) => selected,
+            // 从其他机器复制来的文件只有 .meta, 数据库中没有记录
+            Err(err) => vec![find_sidecar(target).ok_or(err)?],
+        },
+    };
+    let mut tasks = selected
+        .into_iter()
+        .map(|(path, entry)| {
+            let mut task = build_task(&args.template, path, entry)?;
+            // 续传时使用读取记录的数据库
+            task.state_db = Some(db_path.clone());
+            Ok(task)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    match tasks.len() {
+        0 => {
+            println!("{}", t!("msg.resume-empty"));
+            Ok(())
+        }
+        1 => download::download(tasks.remove(0)).await,
+        _ => {
+            download::download_batch(BatchArgs {
+                tasks,
+                jobs: args.jobs,
+                threads: args.template.threads,
+            })
+            .await
+        }
+    }
+}
+
+/// 读取 `<文件>.fdpart.meta`, `target` 可以省略 `.fdpart` 后缀
+fn find_sidecar(target: &str) -> Option<(String, Downloading)> {
+    let path = soft_canonicalize::soft_canonicalize(target).ok()?;
+    let mut part_path = path.clone().into_os_string();
+    part_path.push(".fdpart");
+    [path.into_os_string(), part_path]
+        .into_iter()
+        .find_map(|path| {
+            let entry = Store::load_sidecar(&path)?;
+            Some((path.to_string_lossy().to_string(), entry))
+        })
+}
+
+/// 用数据库中的记录还原下载参数
+fn build_task(template: &DownloadArgs, path: String, entry: Downloading) -> Result<DownloadArgs> {
+    let path = PathBuf::from(path);
+    let mut task = template.clone();
+    task.url = entry.url;
+    task.resume = true;
+    task.force = false;
+    task.save_folder = path.parent().map(PathBuf::from).unwrap_or_default();
+    task.file_name = Some(
+        entry
+            .file_name
+            .strip_suffix(".fdpart")
+            .unwrap_or(&entry.file_name)
+            .to_string(),
+    );
+    entry.options.apply(&mut task)?;
+    Ok(task)
+}
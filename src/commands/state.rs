@@ -0,0 +1,65 @@
+use crate::{
+    args::{StateExportArgs, StateImportArgs},
+    model::export::{ExportedDownload, StateFile},
+    store::Store,
+};
+use color_eyre::Result;
+use std::path::Path;
+use tokio::{
+    fs,
+    io::{self, AsyncReadExt},
+};
+
+pub async fn export(args: StateExportArgs) -> Result<()> {
+    let db_path = Store::locate(args.state_db.as_deref(), Path::new("."), false);
+    let store = Store::new(db_path).await?;
+    let downloads: Vec<_> = store
+        .sorted_entries()
+        .into_iter()
+        .map(|(path, entry)| ExportedDownload::new(path, entry))
+        .collect();
+    let count = downloads.len();
+    let json = serde_json::to_string_pretty(&StateFile::new(downloads))?;
+    match args.output {
+        Some(output) => {
+            fs::write(output, json + "\n").await?;
+            eprintln!("{}", t!("msg.state-exported", count = count));
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+pub async fn import(args: StateImportArgs) -> Result<()> {
+    let content = if args.input == Path::new("-") {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content).await?;
+        content
+    } else {
+        fs::read_to_string(&args.input).await?
+    };
+    let file = StateFile::parse(&content)?;
+    let db_path = Store::locate(args.state_db.as_deref(), Path::new("."), false);
+    let store = Store::new(db_path).await?;
+    let (mut imported, mut skipped, mut missing) = (0, 0, 0);
+    for mut download in file.downloads {
+        download.rebase(&args.rebase);
+        let (path, entry) = download.into_entry()?;
+        if !store.import_entry(&path, &entry, args.overwrite)? {
+            skipped += 1;
+            continue;
+        }
+        imported += 1;
+        if !fs::try_exists(&path).await.unwrap_or(false) {
+            missing += 1;
+        }
+    }
+    println!(
+        "{}",
+        t!("msg.state-imported", imported = imported, skipped = skipped)
+    );
+    if missing > 0 {
+        eprintln!("{}", t!("msg.state-missing-files", count = missing));
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use crate::utils::dirs::config_dir;
+use clap::Command;
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, bail},
+};
+use std::{collections::BTreeMap, env, fs, io::ErrorKind, path::PathBuf};
+use toml::{Table, Value};
+
+/// 配置文件中的默认选项
+///
+/// 键为下载命令的长参数名, 如 `threads`、`write-method`, 顶层的选项对所有下载生效,
+/// `[profile.xxx]` 中的选项在使用 `--profile xxx` 时覆盖顶层选项
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    values: BTreeMap<String, Vec<String>>,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        let content = match Self::path().map(fs::read_to_string) {
+            Some(Ok(content)) => content,
+            Some(Err(err)) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => String::new(),
+        };
+        Self::parse(&content, profile)
+    }
+
+    fn parse(content: &str, profile: Option<&str>) -> Result<Self> {
+        let mut table: Table = content.parse()?;
+        let profiles = table.remove("profile");
+        let mut config = Self::default();
+        config.merge(table)?;
+        if let Some(name) = profile {
+            let profile = profiles
+                .as_ref()
+                .and_then(|profiles| profiles.get(name))
+                .and_then(Value::as_table)
+                .with_context(|| format!("配置文件中找不到配置 {name}"))?;
+            config.merge(profile.clone())?;
+        }
+        Ok(config)
+    }
+
+    fn merge(&mut self, table: Table) -> Result<()> {
+        for (key, value) in table {
+            let values = match value {
+                Value::Array(array) => array
+                    .into_iter()
+                    .map(|v| to_string(&key, v))
+                    .collect::<Result<_>>()?,
+                value => vec![to_string(&key, value)?],
+            };
+            self.values.insert(key, values);
+        }
+        Ok(())
+    }
+
+    /// 把配置写入命令的默认值, 命令行参数与环境变量仍然优先
+    pub fn apply(&self, mut cmd: Command) -> Result<Command> {
+        for (key, values) in &self.values {
+            let id = cmd
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key) && arg.get_id() != "profile")
+                .with_context(|| format!("配置文件中不支持的选项: {key}"))?
+                .get_id()
+                .clone();
+            cmd = cmd.mut_arg(id, |arg| arg.default_values(values));
+        }
+        Ok(cmd)
+    }
+}
+
+fn to_string(key: &str, value: Value) -> Result<String> {
+    Ok(match value {
+        Value::String(s) => s,
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        _ => bail!("配置文件中 {key} 的值格式错误"),
+    })
+}
+
+/// 在解析命令行之前找到 `--profile` 的值, 没有时使用环境变量 `FD_PROFILE`
+pub fn profile_from_args() -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(profile) = arg.strip_prefix("--profile=") {
+            return Some(profile.to_string());
+        }
+    }
+    env::var("FD_PROFILE").ok().filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.iter().map(|s| s.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let content = r#"
+threads = 16
+browser = true
+header = ["Cookie: a=b"]
+
+[profile.work]
+threads = 4
+proxy = "http://127.0.0.1:7890"
+"#;
+        let config = Config::parse(content, None).unwrap();
+        assert_eq!(
+            config.values,
+            values(&[
+                ("browser", &["true"]),
+                ("header", &["Cookie: a=b"]),
+                ("threads", &["16"]),
+            ])
+        );
+        let config = Config::parse(content, Some("work")).unwrap();
+        assert_eq!(
+            config.values,
+            values(&[
+                ("browser", &["true"]),
+                ("header", &["Cookie: a=b"]),
+                ("proxy", &["http://127.0.0.1:7890"]),
+                ("threads", &["4"]),
+            ])
+        );
+        assert!(Config::parse(content, Some("home")).is_err());
+        assert!(Config::parse("threads = { a = 1 }", None).is_err());
+        assert_eq!(Config::parse("", None).unwrap(), Config::default());
+    }
+}
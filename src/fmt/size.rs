@@ -10,6 +10,34 @@ pub fn format_size(mut size: f64) -> String {
     format!("{:.2} {}", size, UNITS[unit_index])
 }
 
+/// [`format_size`] 的逆操作, 支持 `5M`、`1.5GiB`、`512 KB` 等格式, 单位均按 1024 进制计算
+pub fn parse_size(size: &str) -> Option<u64> {
+    const UNITS: &[&str] = &["", "K", "M", "G", "T", "P", "E"];
+
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (num, unit) = size.split_at(split);
+    let num: f64 = num.parse().ok()?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let unit_index = UNITS.iter().position(|u| *u == unit)?;
+    Some((num * 1024f64.powi(unit_index as i32)) as u64)
+}
+
+/// 解析速度, 如 `5M`、`5M/s`, `unlimited` 与 0 代表不限速
+pub fn parse_rate(rate: &str) -> Option<u64> {
+    let rate = rate.trim();
+    if rate.eq_ignore_ascii_case("unlimited") {
+        return Some(0);
+    }
+    parse_size(rate.strip_suffix("/s").unwrap_or(rate))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,4 +91,22 @@ mod tests {
             "1048565.76 YiB"
         );
     }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0"), Some(0));
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("100B"), Some(100));
+        assert_eq!(parse_size("5M"), Some(5 * 1024 * 1024));
+        assert_eq!(parse_size("5m"), Some(5 * 1024 * 1024));
+        assert_eq!(parse_size("512 KB"), Some(512 * 1024));
+        assert_eq!(parse_size("1.5GiB"), Some(1536 * 1024 * 1024));
+        assert_eq!(parse_size(&format_size(1024.0)), Some(1024));
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("5X"), None);
+        assert_eq!(parse_size("-5M"), None);
+        assert_eq!(parse_rate("unlimited"), Some(0));
+        assert_eq!(parse_rate("2M/s"), Some(2 * 1024 * 1024));
+    }
 }
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 pub fn add_prefix_to_lines(s: &str, prefix: &str) -> String {
     let line_count = s.lines().count();
     let mut result = String::with_capacity(s.len() + prefix.len() * line_count);
@@ -16,3 +18,26 @@ pub fn add_prefix_to_lines(s: &str, prefix: &str) -> String {
 
     result
 }
+
+/// 按 RFC 4180 转义 CSV 字段, 含有逗号、引号或换行时加上引号
+pub fn escape_csv(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_csv() {
+        assert_eq!(escape_csv("a.bin"), "a.bin");
+        assert_eq!(escape_csv(""), "");
+        assert_eq!(escape_csv("a,b"), "\"a,b\"");
+        assert_eq!(escape_csv("W/\"abc\""), "\"W/\"\"abc\"\"\"");
+        assert_eq!(escape_csv("a\nb"), "\"a\nb\"");
+    }
+}
@@ -1,3 +1,5 @@
+use chrono::{Local, TimeZone};
+
 const ONE_SECOND: u64 = 1;
 const ONE_MINUTE: u64 = ONE_SECOND * 60;
 const ONE_HOUR: u64 = ONE_MINUTE * 60;
@@ -20,6 +22,14 @@ pub fn format_time(time: u64) -> String {
     }
 }
 
+/// Unix 时间戳转为本地时间, 单位: 秒
+pub fn format_timestamp(timestamp: i64) -> String {
+    Local.timestamp_opt(timestamp, 0).single().map_or_else(
+        || timestamp.to_string(),
+        |time| time.format("%Y-%m-%d %H:%M:%S").to_string(),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2,13 +2,16 @@
 extern crate rust_i18n;
 mod args;
 mod commands;
+mod config;
 mod fmt;
 mod model;
 mod progress;
+mod puller;
+mod pusher;
 mod store;
 mod utils;
 
-use args::Args;
+use args::{Args, ProgressMode};
 use color_eyre::Result;
 use commands::*;
 use mimalloc::MiMalloc;
@@ -31,11 +34,21 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 async fn main() -> Result<()> {
     init_locale();
     color_eyre::install()?;
-    eprintln!("fast-down v{VERSION}");
     let args = Args::parse()?;
+    // JSON 模式下标准错误只输出 JSON
+    if args.progress() != Some(ProgressMode::Json) {
+        eprintln!("fast-down v{VERSION}");
+    }
     match args {
         Args::Download(args) => download::download(args).await,
+        Args::Batch(args) => download::download_batch(args).await,
         // Args::Update => update::update().await,
         Args::List(args) => list::list(args).await,
+        Args::Resume(args) => resume::resume(args).await,
+        Args::Remove(args) => remove::remove(args).await,
+        Args::StateExport(args) => state::export(args).await,
+        Args::StateImport(args) => state::import(args).await,
+        Args::History(args) => history::history(args).await,
+        Args::Stats(args) => history::stats(args).await,
     }
 }
@@ -1,10 +1,19 @@
-use crate::fmt;
+use crate::{args::DownloadArgs, fmt, utils::fingerprint::Piece};
 use bitcode::{Decode, Encode};
+use color_eyre::Result;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
 use std::fmt::Write;
-use std::time::Duration;
+use std::{str::FromStr, time::Duration};
 
 /// 对Progress进行可视化输出的时候每行最多显示个数
 const DISPLAY_PROGRESS_IN_PER_LINE: usize = 3;
+/// 下载选项的版本, 修改 [`DownloadOptions`] 时需要加一并保留旧版本的解码
+const OPTIONS_VERSION: u32 = 1;
+/// 记录开头的标记, 之后的一个字节为记录格式的版本, 没有标记的是旧版记录
+const RECORD_MAGIC: &[u8] = b"FDR";
+/// 记录格式的版本, 修改 [`DownloadingRecord`] 时需要加一并保留旧版本的解码
+const RECORD_VERSION: u8 = 1;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Downloading {
@@ -15,17 +24,148 @@ pub struct Downloading {
     pub last_modified: Option<String>,
     pub progress: Vec<(u64, u64)>,
     pub elapsed: Duration,
+    /// 没有强 ETag 时, 用于续传前判断远程文件是否改变
+    pub fingerprint: Vec<Piece>,
+    pub options: DownloadOptions,
+}
+
+/// 续传时用于还原下载参数的选项
+///
+/// 数值为 0 代表旧版记录中没有保存该选项, 此时使用命令行的默认值
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct DownloadOptions {
+    pub headers: Vec<(String, String)>,
+    pub proxy: Option<String>,
+    pub threads: usize,
+    pub mirrors: Vec<String>,
+    pub browser: bool,
+    pub ips: Vec<String>,
+    pub accept_invalid_certs: bool,
+    pub accept_invalid_hostnames: bool,
+    pub chunk_window: u64,
+    pub min_chunk_size: u64,
+    pub max_speculative: usize,
+    pub retry_gap: u64,    // ms
+    pub pull_timeout: u64, // ms
+}
+
+impl DownloadOptions {
+    pub fn from_args(args: &DownloadArgs) -> Self {
+        Self {
+            headers: args
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+            proxy: args.proxy.clone(),
+            threads: args.threads,
+            mirrors: args.mirrors.clone(),
+            browser: args.browser,
+            ips: args.ips.clone(),
+            accept_invalid_certs: args.accept_invalid_certs,
+            accept_invalid_hostnames: args.accept_invalid_hostnames,
+            chunk_window: args.chunk_window,
+            min_chunk_size: args.min_chunk_size,
+            max_speculative: args.max_speculative,
+            retry_gap: args.retry_gap.as_millis() as u64,
+            pull_timeout: args.pull_timeout.as_millis() as u64,
+        }
+    }
+
+    /// 把记录中的选项写回下载参数
+    pub fn apply(&self, args: &mut DownloadArgs) -> Result<()> {
+        for (name, value) in &self.headers {
+            args.headers
+                .insert(HeaderName::from_str(name)?, HeaderValue::from_str(value)?);
+        }
+        if self.proxy.is_some() {
+            args.proxy = self.proxy.clone();
+        }
+        args.mirrors = self.mirrors.clone();
+        args.browser = self.browser;
+        args.ips = self.ips.clone();
+        args.accept_invalid_certs = self.accept_invalid_certs;
+        args.accept_invalid_hostnames = self.accept_invalid_hostnames;
+        if self.threads > 0 {
+            args.threads = self.threads;
+        }
+        if self.chunk_window > 0 {
+            args.chunk_window = self.chunk_window;
+        }
+        if self.min_chunk_size > 0 {
+            args.min_chunk_size = self.min_chunk_size;
+        }
+        if self.max_speculative > 0 {
+            args.max_speculative = self.max_speculative;
+        }
+        if self.retry_gap > 0 {
+            args.retry_gap = Duration::from_millis(self.retry_gap);
+        }
+        if self.pull_timeout > 0 {
+            args.pull_timeout = Duration::from_millis(self.pull_timeout);
+        }
+        Ok(())
+    }
+}
+
+/// 带版本号的 [`DownloadOptions`], 避免增加选项时整条记录无法解码
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+struct OptionsBlob {
+    version: u32,
+    data: Vec<u8>,
+}
+
+impl OptionsBlob {
+    fn new(options: &DownloadOptions) -> Self {
+        Self {
+            version: OPTIONS_VERSION,
+            data: bitcode::encode(options),
+        }
+    }
+
+    /// 无法识别的版本或无法解码时返回 None, 避免续传时丢失请求头、代理等选项
+    fn options(&self) -> Option<DownloadOptions> {
+        match self.version {
+            OPTIONS_VERSION => bitcode::decode(&self.data).ok(),
+            _ => None,
+        }
+    }
 }
 
 impl Downloading {
     pub fn load(bytes: &[u8]) -> Option<Self> {
-        let record: DownloadingRecord = bitcode::decode(bytes).ok()?;
+        match bytes
+            .strip_prefix(RECORD_MAGIC)
+            .and_then(<[u8]>::split_first)
+        {
+            Some((&RECORD_VERSION, data)) => {
+                if let Ok(record) = bitcode::decode::<DownloadingRecord>(data) {
+                    return Self::try_from(record).ok();
+                }
+            }
+            // 更新版本的程序写入的记录
+            Some((&version, _)) if version > RECORD_VERSION => return None,
+            // 恰好以标记开头的旧版记录
+            _ => {}
+        }
+        let record: DownloadingRecordV1 = bitcode::decode(bytes).ok()?;
         Some(Self::from(record))
     }
 
+    /// 已下载的字节数
+    pub fn downloaded(&self) -> u64 {
+        self.progress.iter().map(|(start, end)| end - start).sum()
+    }
+
     pub fn dump(&self) -> Vec<u8> {
         let record: DownloadingRecord = self.clone().into();
-        bitcode::encode(&record)
+        let mut bytes = RECORD_MAGIC.to_vec();
+        bytes.push(RECORD_VERSION);
+        bytes.extend(bitcode::encode(&record));
+        bytes
     }
 
     #[rustfmt::skip]
@@ -45,6 +185,26 @@ impl Downloading {
                 writeln!(&mut content, "{}: {}", t!("db-display.etag"), etag)?;
             }
 
+            if self.options.threads > 0 {
+                writeln!(&mut content, "{}: {}", t!("db-display.threads"), self.options.threads)?;
+            }
+
+            if let Some(proxy) = &self.options.proxy {
+                writeln!(&mut content, "{}: {}", t!("db-display.proxy"), proxy)?;
+            }
+
+            for (name, value) in &self.options.headers {
+                writeln!(&mut content, "{}: {}: {}", t!("db-display.header"), name, value)?;
+            }
+
+            for mirror in &self.options.mirrors {
+                writeln!(&mut content, "{}: {}", t!("db-display.mirror"), mirror)?;
+            }
+
+            if !self.options.ips.is_empty() {
+                writeln!(&mut content, "{}: {}", t!("db-display.ips"), self.options.ips.join(", "))?;
+            }
+
             write!(&mut content, "{}: ", t!("db-display.progress"))?;
             for (i, (start, end)) in self.progress.iter().enumerate() {
                 if i % DISPLAY_PROGRESS_IN_PER_LINE == 0 {
@@ -73,6 +233,20 @@ struct DownloadingRecord {
     progress: Vec<(u64, u64)>,
     elapsed: u64, // ms
     url: String,
+    fingerprint: Vec<Piece>,
+    options: OptionsBlob,
+}
+
+/// 没有标记的旧版记录, 不包含 fingerprint 和 options
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+struct DownloadingRecordV1 {
+    file_name: String,
+    file_size: u64,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    progress: Vec<(u64, u64)>,
+    elapsed: u64, // ms
+    url: String,
 }
 
 impl From<Downloading> for DownloadingRecord {
@@ -85,12 +259,32 @@ impl From<Downloading> for DownloadingRecord {
             progress: downloading.progress,
             elapsed: downloading.elapsed.as_millis() as u64,
             url: downloading.url,
+            fingerprint: downloading.fingerprint,
+            options: OptionsBlob::new(&downloading.options),
         }
     }
 }
 
-impl From<DownloadingRecord> for Downloading {
-    fn from(record: DownloadingRecord) -> Self {
+impl TryFrom<DownloadingRecord> for Downloading {
+    type Error = ();
+
+    fn try_from(record: DownloadingRecord) -> Result<Self, ()> {
+        Ok(Self {
+            file_name: record.file_name,
+            file_size: record.file_size,
+            etag: record.etag,
+            last_modified: record.last_modified,
+            progress: record.progress,
+            elapsed: Duration::from_millis(record.elapsed),
+            url: record.url,
+            options: record.options.options().ok_or(())?,
+            fingerprint: record.fingerprint,
+        })
+    }
+}
+
+impl From<DownloadingRecordV1> for Downloading {
+    fn from(record: DownloadingRecordV1) -> Self {
         Self {
             file_name: record.file_name,
             file_size: record.file_size,
@@ -99,6 +293,66 @@ impl From<DownloadingRecord> for Downloading {
             progress: record.progress,
             elapsed: Duration::from_millis(record.elapsed),
             url: record.url,
+            fingerprint: Vec::new(),
+            options: DownloadOptions::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_legacy_record() {
+        let v1 = DownloadingRecordV1 {
+            file_name: "a.bin.fdpart".to_string(),
+            file_size: 100,
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            progress: vec![(0, 50)],
+            elapsed: 1000,
+            url: "http://example.com/a.bin".to_string(),
+        };
+        let mut entry = Downloading::load(&bitcode::encode(&v1)).unwrap();
+        assert_eq!(entry.progress, v1.progress);
+        assert_eq!(entry.elapsed, Duration::from_secs(1));
+        assert_eq!(entry.fingerprint, vec![]);
+        assert_eq!(entry.options, DownloadOptions::default());
+
+        entry.fingerprint = vec![(0, 50, [1; 32])];
+        entry.options.headers = vec![("cookie".to_string(), "a=b".to_string())];
+        entry.options.mirrors = vec!["http://mirror.example.com/a.bin".to_string()];
+        entry.options.browser = true;
+        assert_eq!(Downloading::load(&entry.dump()), Some(entry.clone()));
+    }
+
+    #[test]
+    fn test_record_version() {
+        let entry = Downloading {
+            url: "http://example.com/a.bin".to_string(),
+            file_name: "a.bin.fdpart".to_string(),
+            file_size: 100,
+            etag: None,
+            last_modified: None,
+            progress: vec![(0, 50)],
+            elapsed: Duration::from_secs(1),
+            fingerprint: Vec::new(),
+            options: DownloadOptions::default(),
+        };
+        let mut bytes = entry.dump();
+        assert!(bytes.starts_with(RECORD_MAGIC));
+        assert_eq!(bytes[RECORD_MAGIC.len()], RECORD_VERSION);
+        // 更新版本的记录不会被当作旧版记录解码
+        bytes[RECORD_MAGIC.len()] = RECORD_VERSION + 1;
+        assert_eq!(Downloading::load(&bytes), None);
+
+        // 无法识别的选项版本不会被当作默认选项
+        let mut record: DownloadingRecord = entry.into();
+        record.options.version = OPTIONS_VERSION + 1;
+        let mut bytes = RECORD_MAGIC.to_vec();
+        bytes.push(RECORD_VERSION);
+        bytes.extend(bitcode::encode(&record));
+        assert_eq!(Downloading::load(&bytes), None);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    model::downloading::{DownloadOptions, Downloading},
+    utils::checksum::{decode_hex, encode_hex},
+};
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, bail},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// 导出文件的格式版本, 修改 [`ExportedDownload`] 时需要保持兼容或加一
+const EXPORT_VERSION: u32 = 1;
+
+/// `fd state export` 输出的文件
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateFile {
+    pub version: u32,
+    pub downloads: Vec<ExportedDownload>,
+}
+
+impl StateFile {
+    pub fn new(downloads: Vec<ExportedDownload>) -> Self {
+        Self {
+            version: EXPORT_VERSION,
+            downloads,
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let file: Self = serde_json::from_str(content)?;
+        if file.version > EXPORT_VERSION {
+            bail!("不支持的导出文件版本: {}", file.version);
+        }
+        Ok(file)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedDownload {
+    pub path: String,
+    pub url: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub progress: Vec<(u64, u64)>,
+    /// 单位: ms
+    pub elapsed: u64,
+    /// `(start, end, blake3 十六进制)`
+    #[serde(default)]
+    pub fingerprint: Vec<(u64, u64, String)>,
+    #[serde(default)]
+    pub options: DownloadOptions,
+}
+
+impl ExportedDownload {
+    pub fn new(path: String, entry: Downloading) -> Self {
+        Self {
+            path,
+            url: entry.url,
+            file_name: entry.file_name,
+            file_size: entry.file_size,
+            etag: entry.etag,
+            last_modified: entry.last_modified,
+            progress: entry.progress,
+            elapsed: entry.elapsed.as_millis() as u64,
+            fingerprint: entry
+                .fingerprint
+                .iter()
+                .map(|(start, end, hash)| (*start, *end, encode_hex(hash)))
+                .collect(),
+            options: entry.options,
+        }
+    }
+
+    /// 按第一条匹配的 `(旧前缀, 新前缀)` 改写路径
+    pub fn rebase(&mut self, rules: &[(PathBuf, PathBuf)]) {
+        for (old, new) in rules {
+            if let Ok(rest) = Path::new(&self.path).strip_prefix(old) {
+                self.path = new.join(rest).to_string_lossy().to_string();
+                return;
+            }
+        }
+    }
+
+    pub fn into_entry(self) -> Result<(String, Downloading)> {
+        let fingerprint = self
+            .fingerprint
+            .into_iter()
+            .map(|(start, end, hash)| {
+                let hash = decode_hex(&hash)
+                    .and_then(|hash| hash.try_into().ok())
+                    .with_context(|| format!("指纹格式错误: {hash}"))?;
+                Ok((start, end, hash))
+            })
+            .collect::<Result<_>>()?;
+        let entry = Downloading {
+            url: self.url,
+            file_name: self.file_name,
+            file_size: self.file_size,
+            etag: self.etag,
+            last_modified: self.last_modified,
+            progress: self.progress,
+            elapsed: Duration::from_millis(self.elapsed),
+            fingerprint,
+            options: self.options,
+        };
+        Ok((self.path, entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_file() {
+        let entry = Downloading {
+            url: "http://example.com/a.bin".to_string(),
+            file_name: "a.bin.fdpart".to_string(),
+            file_size: 100,
+            etag: Some("W/\"abc\"".to_string()),
+            last_modified: None,
+            progress: vec![(0, 50)],
+            elapsed: Duration::from_millis(1500),
+            fingerprint: vec![(0, 50, [0xab; 32])],
+            options: DownloadOptions {
+                threads: 8,
+                ..Default::default()
+            },
+        };
+        let file = StateFile::new(vec![ExportedDownload::new(
+            "/tmp/a.bin.fdpart".to_string(),
+            entry.clone(),
+        )]);
+        let json = serde_json::to_string(&file).unwrap();
+        let parsed = StateFile::parse(&json).unwrap();
+        assert_eq!(parsed, file);
+        assert_eq!(
+            parsed.downloads[0].clone().into_entry().unwrap(),
+            ("/tmp/a.bin.fdpart".to_string(), entry)
+        );
+
+        assert!(StateFile::parse(r#"{"version":99,"downloads":[]}"#).is_err());
+
+        let rules = [
+            (PathBuf::from("/tmp/a"), PathBuf::from("/data")),
+            (PathBuf::from("/tmp"), PathBuf::from("/home/user")),
+        ];
+        let mut download = file.downloads[0].clone();
+        // 按路径组件匹配, /tmp/a 不匹配 /tmp/a.bin.fdpart
+        download.rebase(&rules);
+        assert_eq!(download.path, "/home/user/a.bin.fdpart");
+        let mut bad = file.downloads[0].clone();
+        bad.fingerprint[0].2 = "abcd".to_string();
+        assert!(bad.into_entry().is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{collections::HashMap, time::Duration};
+use url::Url;
+
+/// 一次已结束的下载
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub url: String,
+    /// 成功时为最终的输出路径, 失败时为 `.fdpart` 文件的路径
+    pub path: String,
+    pub size: u64,
+    /// 包含之前续传的耗时
+    pub elapsed: Duration,
+    pub threads: usize,
+    /// 下载出错或超时后重试的次数
+    pub retries: u64,
+    /// Unix 时间戳, 单位: 秒
+    pub finished_at: i64,
+    /// 失败原因, 成功时为 None
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    /// 单位: B/s
+    pub fn speed(&self) -> f64 {
+        speed(self.size, self.elapsed)
+    }
+
+    pub fn host(&self) -> Option<String> {
+        Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+    }
+}
+
+fn speed(size: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        0.0 => 0.0,
+        secs => size as f64 / secs,
+    }
+}
+
+/// 成功下载的汇总, 失败的下载只计入次数
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub size: u64,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    fn add(&mut self, entry: &HistoryEntry) {
+        if entry.error.is_some() {
+            self.failed += 1;
+        } else {
+            self.succeeded += 1;
+            self.size += entry.size;
+            self.elapsed += entry.elapsed;
+        }
+    }
+
+    /// 单位: B/s
+    pub fn speed(&self) -> f64 {
+        speed(self.size, self.elapsed)
+    }
+}
+
+/// `fd stats` 的统计结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub total: Summary,
+    /// 按下载量从大到小排序
+    pub hosts: Vec<(String, Summary)>,
+}
+
+impl Stats {
+    pub fn new(entries: &[HistoryEntry]) -> Self {
+        let mut total = Summary::default();
+        let mut hosts: HashMap<String, Summary> = HashMap::new();
+        for entry in entries {
+            total.add(entry);
+            if let Some(host) = entry.host() {
+                hosts.entry(host).or_default().add(entry);
+            }
+        }
+        let mut hosts: Vec<_> = hosts.into_iter().collect();
+        hosts.sort_by(|a, b| b.1.size.cmp(&a.1.size).then_with(|| a.0.cmp(&b.0)));
+        Self { total, hosts }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str, size: u64, secs: u64, error: Option<&str>) -> HistoryEntry {
+        HistoryEntry {
+            url: url.to_string(),
+            path: "/tmp/a.bin".to_string(),
+            size,
+            elapsed: Duration::from_secs(secs),
+            threads: 8,
+            retries: 0,
+            finished_at: 0,
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let entries = [
+            entry("http://a.example.com/1", 100, 1, None),
+            entry("http://B.example.com/2", 300, 2, None),
+            entry("http://a.example.com/3", 200, 1, None),
+            entry("http://a.example.com/4", 500, 5, Some("timeout")),
+        ];
+        let stats = Stats::new(&entries);
+        assert_eq!(
+            stats.total,
+            Summary {
+                succeeded: 3,
+                failed: 1,
+                size: 600,
+                elapsed: Duration::from_secs(4),
+            }
+        );
+        assert_eq!(stats.total.speed(), 150.0);
+        let hosts: Vec<_> = stats
+            .hosts
+            .iter()
+            .map(|(host, s)| (host.as_str(), s.succeeded, s.failed, s.speed()))
+            .collect();
+        assert_eq!(
+            hosts,
+            [
+                ("a.example.com", 2, 1, 150.0),
+                ("b.example.com", 1, 0, 150.0)
+            ]
+        );
+        assert_eq!(entry("http://a/", 100, 0, None).speed(), 0.0);
+    }
+}
@@ -1 +1,3 @@
 pub mod downloading;
+pub mod export;
+pub mod history;
@@ -1,8 +1,16 @@
-use crate::fmt;
+use crate::{
+    args::ProgressMode,
+    fmt,
+    progress::{ProgressEvent, Tui, Worker, WorkerState},
+    puller::limit::RateLimiter,
+    utils::schedule::{Schedule, now_seconds},
+};
 use crossterm::{QueueableCommand, cursor, style::Print, terminal};
-use fast_down::{Merge, ProgressEntry, Total};
+use fast_down::{Merge, ProgressEntry, Total, WorkerId};
 use parking_lot::Mutex;
+use serde::Serialize;
 use std::{
+    collections::BTreeMap,
     io::{self, Stderr, Write},
     sync::Arc,
     time::{Duration, Instant},
@@ -11,198 +19,673 @@ use tokio::task::JoinHandle;
 
 const BLOCK_CHARS: [char; 9] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
 
+pub type BarId = usize;
+
+/// `--progress json` 输出的一行
+#[derive(Serialize)]
+struct JsonLine<'a, T: Serialize> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<BarId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(flatten)]
+    record: T,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum PainterRecord<'a> {
+    Progress {
+        bytes: u64,
+        /// 为 0 时大小未知
+        total: u64,
+        /// 单位: B/s
+        speed: f64,
+        /// 单位: 秒
+        eta: Option<u64>,
+        /// 单位: 秒
+        elapsed: u64,
+        workers: usize,
+    },
+    Message {
+        message: &'a str,
+    },
+}
+
+/// 按已完成的区间绘制进度条, 每个字符代表 `file_size / width` 字节, `progress` 需按顺序排列
+pub fn render_blocks(progress: &[ProgressEntry], file_size: u64, width: usize) -> String {
+    let per_bytes = file_size as f64 / width as f64;
+    let mut bar_values = vec![0u64; width];
+    let mut index = 0;
+    for (i, value) in bar_values.iter_mut().enumerate() {
+        let start_byte = i as f64 * per_bytes;
+        let end_byte = (start_byte + per_bytes) as u64;
+        let start_byte = start_byte as u64;
+        let mut block_total = 0;
+        for segment in &progress[index..] {
+            if segment.end <= start_byte {
+                index += 1;
+                continue;
+            }
+            if segment.start >= end_byte {
+                break;
+            }
+            let overlap_start = segment.start.max(start_byte);
+            let overlap_end = segment.end.min(end_byte);
+            if overlap_start < overlap_end {
+                block_total += overlap_end - overlap_start;
+            }
+        }
+        *value = block_total;
+    }
+    bar_values
+        .iter()
+        .map(|&count| {
+            BLOCK_CHARS[((count as f64 / per_bytes * (BLOCK_CHARS.len() - 1) as f64).round()
+                as usize)
+                .min(BLOCK_CHARS.len() - 1)]
+        })
+        .collect()
+}
+
+/// 单个文件的进度条
 #[derive(Debug)]
-pub struct Painter {
+pub struct Bar {
+    /// 多文件下载时显示在进度条上方的名称
+    pub name: Option<String>,
     pub progress: Vec<ProgressEntry>,
-    pub width: u16,
     pub start: Instant,
-    pub alpha: f64,
     pub file_size: u64,
     pub prev_size: u64,
     pub curr_size: u64,
     pub avg_speed: f64,
+    /// 最高平均速度, 按时间表估算剩余时间时视为不限速时的速度
+    pub peak_speed: f64,
+    /// 各线程的状态, 线程结束后仍然保留
+    pub workers: BTreeMap<WorkerId, Worker>,
+    /// plain 模式下上一次输出的时间
+    pub last_print: Instant,
+    /// plain 模式下上一次输出时进度所在的档位, 即百分比除以 `plain_step`
+    pub last_step: u64,
+}
+
+impl Bar {
+    fn render(&self, width: u16) -> String {
+        if self.file_size == 0 {
+            return format!(
+                "|{}| {:>6.2}% ({:>8}/Unknown)",
+                BLOCK_CHARS[0].to_string().repeat(width as usize),
+                0.0,
+                fmt::format_size(self.curr_size as f64),
+            );
+        }
+        let get_percent = (self.curr_size as f64 / self.file_size as f64) * 100.0;
+        format!(
+            "|{}| {:>6.2}% ({:>8}/{})",
+            render_blocks(&self.progress, self.file_size, width.into()),
+            get_percent,
+            fmt::format_size(self.curr_size as f64),
+            fmt::format_size(self.file_size as f64),
+        )
+    }
+
+    /// 剩余时间, 单位: 秒, 文件大小未知时为 None
+    fn eta(&self, schedule: Option<&Schedule>) -> Option<u64> {
+        if self.file_size == 0 {
+            return None;
+        }
+        let remaining = self.file_size.saturating_sub(self.curr_size);
+        match schedule {
+            Some(schedule) => schedule.eta(
+                remaining,
+                self.peak_speed.max(self.avg_speed),
+                now_seconds(),
+            ),
+            None if self.avg_speed == 0.0 => None,
+            None => Some((remaining as f64 / self.avg_speed) as u64),
+        }
+    }
+
+    /// plain 模式下输出的一行
+    fn render_plain(&self, schedule: Option<&Schedule>) -> String {
+        let size = match self.file_size {
+            0 => format!(
+                "{:>6.2}% ({}/Unknown)",
+                0.0,
+                fmt::format_size(self.curr_size as f64)
+            ),
+            file_size => format!(
+                "{:>6.2}% ({}/{})",
+                self.percent(),
+                fmt::format_size(self.curr_size as f64),
+                fmt::format_size(file_size as f64),
+            ),
+        };
+        let line = format!("{size} {}", self.render_desc(schedule));
+        match &self.name {
+            Some(name) => format!("{name}: {line}"),
+            None => line,
+        }
+    }
+
+    fn percent(&self) -> f64 {
+        match self.file_size {
+            0 => 0.0,
+            size => self.curr_size as f64 / size as f64 * 100.0,
+        }
+    }
+
+    fn render_desc(&self, schedule: Option<&Schedule>) -> String {
+        t!(
+            "progress.desc",
+            time_spent = fmt::format_time(self.start.elapsed().as_secs()),
+            time_left = self
+                .eta(schedule)
+                .map_or("Unknown".to_string(), fmt::format_time),
+            speed = fmt::format_size(self.avg_speed) : {:>8},
+        )
+        .to_string()
+    }
+}
+
+#[derive(Debug)]
+pub struct Painter {
+    bars: Vec<(BarId, Bar)>,
+    next_id: BarId,
+    pub width: u16,
+    pub alpha: f64,
     pub repaint_duration: Duration,
     pub last_repaint_time: Instant,
-    has_progress: bool,
+    /// 限速时间表, 用于估算剩余时间
+    pub schedule: Option<Schedule>,
+    pub mode: ProgressMode,
+    /// plain 模式下至少每隔这么久输出一次
+    pub plain_interval: Duration,
+    /// plain 模式下进度每增加多少百分比输出一次, 为 0 时只按时间输出
+    pub plain_step: f64,
+    /// `--tui` 时的全屏界面
+    pub tui: Option<Tui>,
+    /// 用于显示暂停状态, 暂停的时间不计入已用时间
+    limiter: Option<RateLimiter>,
+    /// 上一次绘制时累计暂停的时长
+    paused_time: Duration,
+    /// 上一次绘制占用的行数
+    drawn_lines: u16,
     stderr: Stderr,
 }
 
 impl Painter {
     pub fn new(
-        init_progress: Vec<ProgressEntry>,
-        file_size: u64,
         progress_width: u16,
         alpha: f64,
         repaint_duration: Duration,
-        start: Instant,
+        mode: ProgressMode,
     ) -> io::Result<Self> {
-        let init_size = init_progress.total();
+        let mode = mode.resolve();
         let mut stderr = io::stderr();
-        stderr.queue(cursor::Hide)?;
+        if matches!(mode, ProgressMode::Bar | ProgressMode::Detailed) {
+            stderr.queue(cursor::Hide)?;
+        }
         Ok(Self {
-            progress: init_progress,
-            file_size,
+            bars: Vec::new(),
+            next_id: 0,
             width: progress_width,
             alpha,
             repaint_duration,
-            start,
-            prev_size: init_size,
-            curr_size: init_size,
-            avg_speed: 0.0,
             last_repaint_time: Instant::now(),
-            has_progress: false,
+            schedule: None,
+            mode,
+            plain_interval: Duration::from_secs(10),
+            plain_step: 10.0,
+            tui: None,
+            limiter: None,
+            paused_time: Duration::ZERO,
+            drawn_lines: 0,
             stderr,
         })
     }
 
-    pub fn reset_progress(&mut self) {
-        self.progress.clear();
-        self.prev_size = 0;
-        self.curr_size = 0;
-        self.avg_speed = 0.0;
-        self.start = Instant::now();
+    /// 是否需要定时重绘
+    pub fn enabled(&self) -> bool {
+        match self.mode {
+            ProgressMode::Auto | ProgressMode::Bar | ProgressMode::Detailed => self.width > 0,
+            ProgressMode::Plain | ProgressMode::Json | ProgressMode::Tui => true,
+            ProgressMode::None => false,
+        }
+    }
+
+    pub fn set_limiter(&mut self, limiter: RateLimiter) {
+        self.paused_time = limiter.paused_time();
+        self.limiter = Some(limiter);
+    }
+
+    pub fn add_bar(
+        &mut self,
+        name: Option<String>,
+        init_progress: Vec<ProgressEntry>,
+        file_size: u64,
+        start: Instant,
+    ) -> BarId {
+        let init_size = init_progress.total();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.bars.push((
+            id,
+            Bar {
+                name,
+                progress: init_progress,
+                start,
+                file_size,
+                prev_size: init_size,
+                curr_size: init_size,
+                avg_speed: 0.0,
+                peak_speed: 0.0,
+                workers: BTreeMap::new(),
+                last_print: Instant::now(),
+                last_step: 0,
+            },
+        ));
+        id
+    }
+
+    pub fn remove_bar(&mut self, id: BarId) {
+        self.bars.retain(|(bar_id, _)| *bar_id != id);
+    }
+
+    fn bar_mut(&mut self, id: BarId) -> Option<&mut Bar> {
+        self.bars
+            .iter_mut()
+            .find(|(bar_id, _)| *bar_id == id)
+            .map(|(_, bar)| bar)
+    }
+
+    pub fn reset_progress(&mut self, id: BarId) {
+        if let Some(bar) = self.bar_mut(id) {
+            bar.progress.clear();
+            bar.prev_size = 0;
+            bar.curr_size = 0;
+            bar.avg_speed = 0.0;
+            bar.peak_speed = 0.0;
+            bar.workers.clear();
+            bar.start = Instant::now();
+        }
     }
 
     pub fn start_update_thread(painter_arc: Arc<Mutex<Self>>) -> JoinHandle<()> {
         let duration = {
             let painter = painter_arc.lock();
-            assert_ne!(painter.width, 0);
+            assert!(painter.enabled());
             painter.repaint_duration
         };
         tokio::spawn(async move {
             loop {
-                let should_stop = {
-                    let mut painter = painter_arc.lock();
-                    painter.update().unwrap();
-                    painter.file_size > 0 && painter.curr_size >= painter.file_size
-                };
-                if should_stop {
-                    break;
-                }
+                painter_arc.lock().update().unwrap();
                 tokio::time::sleep(duration).await;
             }
         })
     }
 
-    pub fn add(&mut self, p: ProgressEntry) {
-        if self.width == 0 {
+    pub fn add(&mut self, id: BarId, worker: WorkerId, p: ProgressEntry) {
+        if !self.enabled() {
             return;
         }
-        self.progress.merge_progress(p);
-        self.curr_size = self.progress.total();
+        if let Some(bar) = self.bar_mut(id) {
+            bar.workers.entry(worker).or_default().add(p.clone());
+            bar.progress.merge_progress(p);
+            bar.curr_size = bar.progress.total();
+        }
     }
 
     fn reset_pos(&mut self) -> io::Result<()> {
-        if self.has_progress {
+        if self.drawn_lines > 0 {
             self.stderr
-                .queue(cursor::MoveUp(1))?
-                .queue(cursor::MoveUp(1))?
+                .queue(cursor::MoveUp(self.drawn_lines))?
                 .queue(cursor::MoveToColumn(0))?;
         }
         Ok(())
     }
 
-    pub fn update(&mut self) -> io::Result<()> {
-        if self.width == 0 {
-            return Ok(());
+    fn print_line(&mut self, line: &str) -> io::Result<()> {
+        self.stderr.queue(Print(line))?;
+        self.stderr
+            .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        self.stderr.queue(Print("\n"))?;
+        Ok(())
+    }
+
+    /// 序列化为不含换行的一行 JSON
+    fn json_line<T: Serialize>(&self, id: Option<BarId>, record: T) -> serde_json::Result<String> {
+        let name = id
+            .and_then(|id| self.bars.iter().find(|(bar_id, _)| *bar_id == id))
+            .and_then(|(_, bar)| bar.name.as_deref());
+        serde_json::to_string(&JsonLine { id, name, record })
+    }
+
+    fn write_json<T: Serialize>(&mut self, id: Option<BarId>, record: T) -> io::Result<()> {
+        let line = self.json_line(id, record)?;
+        writeln!(self.stderr, "{line}")
+    }
+
+    /// 记录下载事件, 进度条模式下输出为文本
+    pub fn event(&mut self, id: BarId, event: ProgressEvent, verbose: bool) -> io::Result<()> {
+        let state = match event {
+            ProgressEvent::Pulling { worker } => Some((worker, WorkerState::Connecting)),
+            ProgressEvent::PullError { worker, .. } => Some((worker, WorkerState::Retrying)),
+            ProgressEvent::PullTimeout { worker } => Some((worker, WorkerState::TimedOut)),
+            ProgressEvent::Finished { worker } => Some((worker, WorkerState::Finished)),
+            _ => None,
+        };
+        if let Some((worker, state)) = state
+            && let Some(bar) = self.bar_mut(id)
+        {
+            let worker = bar.workers.entry(worker).or_default();
+            match state {
+                // 开始下载新的区间
+                WorkerState::Connecting => worker.range = None,
+                WorkerState::Retrying | WorkerState::TimedOut => worker.retries += 1,
+                _ => {}
+            }
+            worker.state = state;
+        }
+        match self.mode {
+            ProgressMode::Json => {
+                self.write_json(Some(id), &event)?;
+                self.stderr.flush()
+            }
+            _ => match event.describe(verbose) {
+                Some(text) => self.print(&text),
+                None => Ok(()),
+            },
         }
+    }
+
+    /// 把各进度条的开始时间推后, 使暂停的时间不计入已用时间
+    fn skip_paused(&mut self) {
+        let Some(limiter) = &self.limiter else {
+            return;
+        };
+        let paused_time = limiter.paused_time();
+        let paused = paused_time.saturating_sub(self.paused_time);
+        self.paused_time = paused_time;
+        for (_, bar) in &mut self.bars {
+            bar.start += paused;
+        }
+        if let Some(tui) = &mut self.tui {
+            tui.paused = limiter.is_paused();
+        }
+    }
+
+    /// 按重绘间隔更新平均速度
+    fn update_speed(&mut self) {
         let repaint_elapsed = self.last_repaint_time.elapsed().as_millis();
         self.last_repaint_time = Instant::now();
-        let curr_dsize = self.curr_size - self.prev_size;
-        self.prev_size = self.curr_size;
-        let curr_speed = if repaint_elapsed > 0 {
-            (curr_dsize * 1000) as f64 / repaint_elapsed as f64
-        } else {
-            0.0
+        let alpha = self.alpha;
+        let smooth = |avg_speed: f64, curr_dsize: u64| {
+            let curr_speed = if repaint_elapsed > 0 {
+                (curr_dsize * 1000) as f64 / repaint_elapsed as f64
+            } else {
+                0.0
+            };
+            avg_speed * alpha + curr_speed * (1.0 - alpha)
         };
-        self.avg_speed = self.avg_speed * self.alpha + curr_speed * (1.0 - self.alpha);
-        let line1 = if self.file_size == 0 {
-            format!(
-                "|{}| {:>6.2}% ({:>8}/Unknown)",
-                BLOCK_CHARS[0].to_string().repeat(self.width as usize),
-                0.0,
-                fmt::format_size(self.curr_size as f64),
-            )
-        } else {
-            let get_percent = (self.curr_size as f64 / self.file_size as f64) * 100.0;
-            let per_bytes = self.file_size as f64 / self.width as f64;
-            let mut bar_values = vec![0u64; self.width as usize];
-            let mut index = 0;
-            for i in 0..self.width {
-                let start_byte = i as f64 * per_bytes;
-                let end_byte = (start_byte + per_bytes) as u64;
-                let start_byte = start_byte as u64;
-                let mut block_total = 0;
-                for segment in &self.progress[index..] {
-                    if segment.end <= start_byte {
-                        index += 1;
-                        continue;
-                    }
-                    if segment.start >= end_byte {
-                        break;
-                    }
-                    let overlap_start = segment.start.max(start_byte);
-                    let overlap_end = segment.end.min(end_byte);
-                    if overlap_start < overlap_end {
-                        block_total += overlap_end - overlap_start;
-                    }
-                }
-                bar_values[i as usize] = block_total;
+        for (_, bar) in &mut self.bars {
+            bar.avg_speed = smooth(bar.avg_speed, bar.curr_size - bar.prev_size);
+            bar.prev_size = bar.curr_size;
+            bar.peak_speed = bar.peak_speed.max(bar.avg_speed);
+            for worker in bar.workers.values_mut() {
+                worker.avg_speed = smooth(worker.avg_speed, worker.curr_size - worker.prev_size);
+                worker.prev_size = worker.curr_size;
+            }
+        }
+    }
+
+    fn update_json(&mut self) -> io::Result<()> {
+        let records: Vec<_> = self
+            .bars
+            .iter()
+            .map(|(id, bar)| {
+                let record = PainterRecord::Progress {
+                    bytes: bar.curr_size,
+                    total: bar.file_size,
+                    speed: bar.avg_speed.round(),
+                    eta: bar
+                        .eta(self.schedule.as_ref())
+                        .filter(|_| bar.avg_speed > 0.0),
+                    elapsed: bar.start.elapsed().as_secs(),
+                    workers: bar
+                        .workers
+                        .values()
+                        .filter(|worker| worker.state != WorkerState::Finished)
+                        .count(),
+                };
+                (*id, record)
+            })
+            .collect();
+        for (id, record) in records {
+            self.write_json(Some(id), record)?;
+        }
+        self.stderr.flush()
+    }
+
+    /// 距离上次输出超过 `plain_interval` 或进度跨过一档时输出一行
+    fn update_plain(&mut self) -> io::Result<()> {
+        let mut lines = Vec::new();
+        for (_, bar) in &mut self.bars {
+            let step = match self.plain_step {
+                step if step > 0.0 && bar.file_size > 0 => (bar.percent() / step) as u64,
+                _ => 0,
+            };
+            if step > bar.last_step || bar.last_print.elapsed() >= self.plain_interval {
+                bar.last_step = step;
+                bar.last_print = Instant::now();
+                lines.push(bar.render_plain(self.schedule.as_ref()));
             }
-            let bar_str: String = bar_values
+        }
+        for line in lines {
+            writeln!(self.stderr, "{line}")?;
+        }
+        self.stderr.flush()
+    }
+
+    /// 在进度条下方列出未结束的线程, 超出终端高度的部分只显示数量
+    fn render_workers(&self, lines: &mut Vec<String>) {
+        let rows = match terminal::size() {
+            Ok((_, rows)) if rows > 0 => rows as usize,
+            _ => usize::MAX,
+        };
+        let mut available = rows.saturating_sub(lines.len() + 1);
+        for (_, bar) in &self.bars {
+            let workers: Vec<_> = bar
+                .workers
                 .iter()
-                .map(|&count| {
-                    BLOCK_CHARS
-                        [((count as f64 / per_bytes * (BLOCK_CHARS.len() - 1) as f64).round()
-                            as usize)
-                            .min(BLOCK_CHARS.len() - 1)]
-                })
+                .filter(|(_, worker)| worker.state != WorkerState::Finished)
                 .collect();
-            format!(
-                "|{}| {:>6.2}% ({:>8}/{})",
-                bar_str,
-                get_percent,
-                fmt::format_size(self.curr_size as f64),
-                fmt::format_size(self.file_size as f64),
-            )
-        };
-        let line2 = t!(
-            "progress.desc",
-            time_spent = fmt::format_time(self.start.elapsed().as_secs()),
-            time_left = if self.file_size == 0 { "Unknown".to_string() } else { fmt::format_time(((self.file_size - self.curr_size) as f64 / self.avg_speed) as u64) },
-            speed = fmt::format_size(self.avg_speed) : {:>8},
-        );
+            if workers.is_empty() {
+                continue;
+            }
+            if let Some(name) = &bar.name {
+                if available == 0 {
+                    break;
+                }
+                lines.push(name.clone());
+                available -= 1;
+            }
+            // 显示不下时留出一行显示剩余的数量
+            let shown = match workers.len() {
+                len if len <= available => len,
+                _ => available.saturating_sub(1),
+            };
+            for (id, worker) in &workers[..shown] {
+                lines.push(worker.render(**id));
+            }
+            available -= shown;
+            if shown < workers.len() && available > 0 {
+                let count = workers.len() - shown;
+                lines.push(t!("progress.worker-more", count = count).to_string());
+                available -= 1;
+            }
+        }
+    }
+
+    pub fn update(&mut self) -> io::Result<()> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        self.skip_paused();
+        self.update_speed();
+        if let Some(tui) = &mut self.tui {
+            return tui.render(&mut self.stderr, &self.bars);
+        }
+        match self.mode {
+            ProgressMode::Json => return self.update_json(),
+            ProgressMode::Plain => return self.update_plain(),
+            _ => {}
+        }
+        let mut lines = Vec::with_capacity(self.bars.len() * 3);
+        for (_, bar) in &self.bars {
+            if let Some(name) = &bar.name {
+                lines.push(name.clone());
+            }
+            lines.push(bar.render(self.width));
+            lines.push(bar.render_desc(self.schedule.as_ref()));
+        }
+        if self.mode == ProgressMode::Detailed {
+            self.render_workers(&mut lines);
+        }
         self.reset_pos()?;
-        self.stderr.queue(Print(line1))?;
-        self.stderr
-            .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
-        self.stderr.queue(Print("\n"))?;
-        self.stderr.queue(Print(&line2))?;
+        for line in &lines {
+            self.print_line(line)?;
+        }
         self.stderr
-            .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
-        self.stderr.queue(Print("\n"))?;
+            .queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
         self.stderr.flush()?;
-        self.has_progress = true;
+        self.drawn_lines = lines.len() as u16;
         Ok(())
     }
 
     pub fn print(&mut self, msg: &str) -> io::Result<()> {
+        if self.mode == ProgressMode::Json {
+            self.write_json(
+                None,
+                PainterRecord::Message {
+                    message: msg.trim_end(),
+                },
+            )?;
+            return self.stderr.flush();
+        }
+        if let Some(tui) = &mut self.tui {
+            return tui.log(&mut self.stderr, msg);
+        }
+        if matches!(self.mode, ProgressMode::Plain | ProgressMode::None) {
+            for line in msg.lines() {
+                writeln!(self.stderr, "{line}")?;
+            }
+            return self.stderr.flush();
+        }
         self.reset_pos()?;
         for line in msg.lines() {
-            self.stderr.queue(Print(line))?;
-            self.stderr
-                .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
-            self.stderr.queue(Print("\n"))?;
+            self.print_line(line)?;
         }
-        self.has_progress = false;
+        self.stderr.flush()?;
+        self.drawn_lines = 0;
         self.update()?;
         Ok(())
     }
+
+    /// 暂时退出全屏界面, 用于在终端中等待用户输入
+    pub fn suspend_tui(&mut self) -> io::Result<()> {
+        match &mut self.tui {
+            Some(tui) => tui.suspend(&mut self.stderr),
+            None => Ok(()),
+        }
+    }
+
+    pub fn resume_tui(&mut self) {
+        if let Some(tui) = &mut self.tui {
+            tui.resume();
+        }
+    }
+
+    /// 退出全屏界面, 之后的输出不再经过全屏界面
+    pub fn close_tui(&mut self) -> io::Result<()> {
+        match self.tui.take() {
+            Some(mut tui) => tui.leave(&mut self.stderr),
+            None => Ok(()),
+        }
+    }
 }
 
 impl Drop for Painter {
     fn drop(&mut self) {
+        if let Some(tui) = &mut self.tui {
+            let _ = tui.leave(&mut self.stderr);
+        }
+        if !matches!(self.mode, ProgressMode::Bar | ProgressMode::Detailed) {
+            return;
+        }
         let _ = self.stderr.queue(cursor::Show);
         let _ = self.stderr.flush();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(file_size: u64, curr_size: u64, avg_speed: f64) -> Bar {
+        Bar {
+            name: None,
+            progress: Vec::new(),
+            start: Instant::now(),
+            file_size,
+            prev_size: curr_size,
+            curr_size,
+            avg_speed,
+            peak_speed: avg_speed,
+            workers: BTreeMap::new(),
+            last_print: Instant::now(),
+            last_step: 0,
+        }
+    }
+
+    #[test]
+    fn test_eta() {
+        assert_eq!(bar(1000, 500, 100.0).eta(None), Some(5));
+        assert_eq!(bar(1000, 500, 0.0).eta(None), None);
+        // 没有 Content-Length 时文件大小为 0, 已下载的大小会超过它
+        assert_eq!(bar(0, 500, 100.0).eta(None), None);
+        assert!(bar(0, 500, 100.0).render_desc(None).contains("Unknown"));
+    }
+
+    #[test]
+    fn test_render_blocks_wide() {
+        // 全屏界面中宽度为列数乘行数, 可能超过 u16 的范围
+        let width = u16::MAX as usize + 10;
+        #[allow(clippy::single_range_in_vec_init)]
+        let progress = vec![0..width as u64 / 2];
+        let blocks = render_blocks(&progress, width as u64, width);
+        assert_eq!(blocks.chars().count(), width);
+        assert_eq!(blocks.chars().filter(|&c| c == '█').count(), width / 2);
+    }
+
+    #[test]
+    fn test_json_message() {
+        let mut painter = Painter::new(0, 0.9, Duration::from_secs(1), ProgressMode::Json).unwrap();
+        let id = painter.add_bar(Some("a.bin".to_string()), Vec::new(), 100, Instant::now());
+        let message = "文件名: a.bin\n路径: \"/tmp/a.bin\"";
+        let line = painter
+            .json_line(None, PainterRecord::Message { message })
+            .unwrap();
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["event"], "message");
+        assert_eq!(value["message"], message);
+        assert!(value.get("id").is_none());
+
+        let event = ProgressEvent::Flushing;
+        let value: serde_json::Value =
+            serde_json::from_str(&painter.json_line(Some(id), &event).unwrap()).unwrap();
+        assert_eq!(value["id"], id);
+        assert_eq!(value["name"], "a.bin");
+    }
+}
@@ -0,0 +1,77 @@
+use fast_down::WorkerId;
+use serde::Serialize;
+
+/// 下载过程中的事件, `--progress json` 时每个事件输出为一行 JSON
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    Pulling { worker: WorkerId },
+    PullError { worker: WorkerId, error: String },
+    PullTimeout { worker: WorkerId },
+    PushError { worker: WorkerId, error: String },
+    Flushing,
+    FlushError { error: String },
+    Finished { worker: WorkerId },
+}
+
+impl ProgressEvent {
+    /// 进度条模式下输出的文本, `Pulling` 和 `Finished` 只在详细输出时显示
+    pub fn describe(&self, verbose: bool) -> Option<String> {
+        let text = match self {
+            Self::Pulling { worker } if verbose => format!(
+                "{} {}\n",
+                t!("verbose.worker-id", id = worker),
+                t!("verbose.downloading")
+            ),
+            Self::Finished { worker } if verbose => format!(
+                "{} {}\n",
+                t!("verbose.worker-id", id = worker),
+                t!("verbose.finished")
+            ),
+            Self::Pulling { .. } | Self::Finished { .. } => return None,
+            Self::PullError { worker, error } => format!(
+                "{} {}\n{}\n",
+                t!("verbose.worker-id", id = worker),
+                t!("verbose.download-error"),
+                error
+            ),
+            Self::PullTimeout { worker } => format!(
+                "{} {}\n",
+                t!("verbose.worker-id", id = worker),
+                t!("verbose.pull-timeout")
+            ),
+            Self::PushError { error, .. } | Self::FlushError { error } => {
+                format!("{}\n{}\n", t!("verbose.write-error"), error)
+            }
+            Self::Flushing => format!("{}\n", t!("verbose.flushing")),
+        };
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_event() {
+        let event = ProgressEvent::PullError {
+            worker: 3,
+            error: "timeout".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"pull_error","worker":3,"error":"timeout"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&ProgressEvent::Flushing).unwrap(),
+            r#"{"event":"flushing"}"#
+        );
+        assert_eq!(ProgressEvent::Pulling { worker: 0 }.describe(false), None);
+        assert!(
+            ProgressEvent::Pulling { worker: 0 }
+                .describe(true)
+                .is_some()
+        );
+    }
+}
@@ -1,3 +1,9 @@
 mod draw;
+mod event;
+mod tui;
+mod worker;
 
 pub use draw::*;
+pub use event::*;
+pub use tui::*;
+pub use worker::*;
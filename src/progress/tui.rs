@@ -0,0 +1,337 @@
+use crate::{
+    fmt,
+    progress::{Bar, BarId, WorkerState, render_blocks},
+};
+use crossterm::{
+    QueueableCommand, cursor,
+    event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    style::Print,
+    terminal,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Stderr, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// 日志最多保留的行数
+const LOG_CAPACITY: usize = 200;
+/// 只有一个文件时分块图占用的行数
+const MAP_ROWS: usize = 4;
+/// 无法获取终端大小时使用的列数和行数
+const DEFAULT_SIZE: (usize, usize) = (80, 24);
+
+/// TUI 中的按键对应的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuiCommand {
+    TogglePause,
+    AddThread,
+    RemoveThread,
+    Quit,
+}
+
+impl TuiCommand {
+    fn from_key(key: KeyEvent) -> Option<Self> {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(Self::Quit),
+            // 输入结束时终端可能发送 NUL, 会被解析为 Ctrl+Space
+            _ if key
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                None
+            }
+            KeyCode::Char(' ' | 'p') => Some(Self::TogglePause),
+            KeyCode::Char('+' | '=') => Some(Self::AddThread),
+            KeyCode::Char('-') => Some(Self::RemoveThread),
+            KeyCode::Char('q') | KeyCode::Esc => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// `--tui` 的全屏界面, 在第一次绘制时进入备用屏幕
+#[derive(Debug)]
+pub struct Tui {
+    tx: UnboundedSender<TuiCommand>,
+    /// 是否已进入全屏界面
+    active: bool,
+    stop: Arc<AtomicBool>,
+    input: Option<JoinHandle<()>>,
+    /// 每次重绘时的总速度
+    speeds: VecDeque<f64>,
+    logs: VecDeque<String>,
+    /// 全屏期间的日志, 退出时输出到终端
+    pending_logs: VecDeque<String>,
+    pub paused: bool,
+    /// 总线程数
+    pub threads: Option<usize>,
+    /// 等待用户输入时暂时退出全屏界面, 为 0 时才会绘制
+    suspended: usize,
+}
+
+impl Tui {
+    pub fn new(tx: UnboundedSender<TuiCommand>) -> Self {
+        Self {
+            tx,
+            active: false,
+            stop: Arc::new(AtomicBool::new(false)),
+            input: None,
+            speeds: VecDeque::new(),
+            logs: VecDeque::new(),
+            pending_logs: VecDeque::new(),
+            paused: false,
+            threads: None,
+            suspended: 0,
+        }
+    }
+
+    fn enter(&mut self, stderr: &mut Stderr) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        self.stop.store(false, Ordering::Relaxed);
+        stderr
+            .queue(terminal::EnterAlternateScreen)?
+            .queue(cursor::Hide)?;
+        self.active = true;
+        let tx = self.tx.clone();
+        let stop = self.stop.clone();
+        // 原始模式下 Ctrl-C 不会产生信号, 由这里转换为退出
+        self.input = Some(std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match event::poll(Duration::from_millis(100)) {
+                    Ok(true) => {
+                        if let Ok(TermEvent::Key(key)) = event::read()
+                            && key.kind == KeyEventKind::Press
+                            && let Some(command) = TuiCommand::from_key(key)
+                            && tx.send(command).is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(_) => break,
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    /// 退出全屏界面, 并输出全屏期间的日志
+    pub fn leave(&mut self, stderr: &mut Stderr) -> io::Result<()> {
+        if !self.active {
+            return Ok(());
+        }
+        self.active = false;
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(input) = self.input.take() {
+            let _ = input.join();
+        }
+        stderr
+            .queue(terminal::LeaveAlternateScreen)?
+            .queue(cursor::Show)?;
+        terminal::disable_raw_mode()?;
+        for line in self.pending_logs.drain(..) {
+            writeln!(stderr, "{line}")?;
+        }
+        stderr.flush()
+    }
+
+    /// 暂时退出全屏界面, 直到对应的 [`Tui::resume`] 之后的下一次绘制
+    pub fn suspend(&mut self, stderr: &mut Stderr) -> io::Result<()> {
+        self.suspended += 1;
+        self.leave(stderr)
+    }
+
+    pub fn resume(&mut self) {
+        self.suspended = self.suspended.saturating_sub(1);
+    }
+
+    /// 全屏前直接输出, 全屏后显示在日志区域
+    pub fn log(&mut self, stderr: &mut Stderr, msg: &str) -> io::Result<()> {
+        for line in msg.lines() {
+            if self.active {
+                push_bounded(&mut self.pending_logs, line.to_string());
+            } else {
+                writeln!(stderr, "{line}")?;
+            }
+            push_bounded(&mut self.logs, line.to_string());
+        }
+        stderr.flush()
+    }
+
+    pub fn render(&mut self, stderr: &mut Stderr, bars: &[(BarId, Bar)]) -> io::Result<()> {
+        if self.suspended > 0 || (bars.is_empty() && !self.active) {
+            return Ok(());
+        }
+        if !self.active {
+            self.enter(stderr)?;
+        }
+        // 部分伪终端报告的大小为 0
+        let (cols, rows) = match terminal::size()? {
+            (0, _) | (_, 0) => DEFAULT_SIZE,
+            (cols, rows) => (cols as usize, rows as usize),
+        };
+        let speed = bars
+            .iter()
+            .map(|(_, bar)| bar.avg_speed)
+            .fold(0.0, |a, b| a + b);
+        push_bounded(&mut self.speeds, speed);
+        while self.speeds.len() > cols {
+            self.speeds.pop_front();
+        }
+
+        let mut lines = vec![self.render_header(bars, speed)];
+        let map_rows = if bars.len() == 1 { MAP_ROWS } else { 1 };
+        for (_, bar) in bars {
+            let percent = match bar.file_size {
+                0 => 0.0,
+                size => bar.curr_size as f64 / size as f64 * 100.0,
+            };
+            lines.push(format!(
+                "{} {:.2}% ({}/{})",
+                bar.name.as_deref().unwrap_or_default(),
+                percent,
+                fmt::format_size(bar.curr_size as f64),
+                fmt::format_size(bar.file_size as f64)
+            ));
+            lines.extend(render_map(bar, cols, map_rows));
+        }
+        lines.push(String::new());
+        let peak = self.speeds.iter().copied().fold(0.0, f64::max);
+        lines.push(t!("progress.tui-throughput", speed = fmt::format_size(peak)).to_string());
+        lines.push(render_sparkline(&self.speeds, peak));
+        lines.push(String::new());
+        lines.push(t!("progress.tui-workers").to_string());
+        let workers: Vec<_> = bars
+            .iter()
+            .flat_map(|(_, bar)| &bar.workers)
+            .filter(|(_, worker)| worker.state != WorkerState::Finished)
+            .collect();
+        // 日志至少保留 3 行, 最后一行显示按键说明
+        let available = rows.saturating_sub(lines.len() + 6);
+        for (id, worker) in workers.iter().take(available) {
+            lines.push(worker.render(**id));
+        }
+        if workers.len() > available {
+            lines.push(t!("progress.worker-more", count = workers.len() - available).to_string());
+        }
+        lines.push(String::new());
+        lines.push(t!("progress.tui-log").to_string());
+        let log_rows = rows.saturating_sub(lines.len() + 1);
+        let skip = self.logs.len().saturating_sub(log_rows);
+        lines.extend(self.logs.iter().skip(skip).cloned());
+
+        stderr.queue(cursor::MoveTo(0, 0))?;
+        for (row, line) in lines.iter().take(rows.saturating_sub(1)).enumerate() {
+            stderr
+                .queue(cursor::MoveTo(0, row as u16))?
+                .queue(Print(truncate(line, cols)))?
+                .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        }
+        stderr
+            .queue(terminal::Clear(terminal::ClearType::FromCursorDown))?
+            .queue(cursor::MoveTo(0, rows.saturating_sub(1) as u16))?
+            .queue(Print(truncate(&t!("progress.tui-help"), cols)))?;
+        stderr.flush()
+    }
+
+    fn render_header(&self, bars: &[(BarId, Bar)], speed: f64) -> String {
+        let curr_size: u64 = bars.iter().map(|(_, bar)| bar.curr_size).sum();
+        let file_size: u64 = bars.iter().map(|(_, bar)| bar.file_size).sum();
+        let mut header = format!(
+            "fast-down  {}/{}  {}/s",
+            fmt::format_size(curr_size as f64),
+            fmt::format_size(file_size as f64),
+            fmt::format_size(speed)
+        );
+        if let Some(threads) = self.threads {
+            header += &format!("  {}", t!("progress.tui-threads", count = threads));
+        }
+        if self.paused {
+            header += &format!("  [{}]", t!("progress.tui-paused"));
+        }
+        header
+    }
+}
+
+fn push_bounded<T>(queue: &mut VecDeque<T>, item: T) {
+    if queue.len() >= LOG_CAPACITY {
+        queue.pop_front();
+    }
+    queue.push_back(item);
+}
+
+fn truncate(line: &str, cols: usize) -> String {
+    line.chars().take(cols).collect()
+}
+
+/// 把整个文件的分块图折成 `rows` 行
+fn render_map(bar: &Bar, cols: usize, rows: usize) -> Vec<String> {
+    let width = cols.saturating_sub(2).max(1);
+    if bar.file_size == 0 {
+        return vec![format!("|{}|", " ".repeat(width))];
+    }
+    let mut progress = bar.progress.clone();
+    progress.sort_unstable_by_key(|r| r.start);
+    let blocks: Vec<char> = render_blocks(&progress, bar.file_size, width * rows)
+        .chars()
+        .collect();
+    blocks
+        .chunks(width)
+        .map(|row| format!("|{}|", row.iter().collect::<String>()))
+        .collect()
+}
+
+fn render_sparkline(speeds: &VecDeque<f64>, peak: f64) -> String {
+    speeds
+        .iter()
+        .map(|speed| match peak {
+            0.0 => SPARK_CHARS[0],
+            peak => {
+                let index = (speed / peak * (SPARK_CHARS.len() - 1) as f64).round() as usize;
+                SPARK_CHARS[index.min(SPARK_CHARS.len() - 1)]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tui_command() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert_eq!(
+            TuiCommand::from_key(key(KeyCode::Char(' '))),
+            Some(TuiCommand::TogglePause)
+        );
+        assert_eq!(
+            TuiCommand::from_key(key(KeyCode::Char('+'))),
+            Some(TuiCommand::AddThread)
+        );
+        assert_eq!(
+            TuiCommand::from_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(TuiCommand::Quit)
+        );
+        assert_eq!(TuiCommand::from_key(key(KeyCode::Char('c'))), None);
+        assert_eq!(
+            TuiCommand::from_key(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::CONTROL)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render_sparkline() {
+        let speeds = VecDeque::from([0.0, 50.0, 100.0]);
+        assert_eq!(render_sparkline(&speeds, 100.0), "▁▅█");
+        assert_eq!(render_sparkline(&speeds, 0.0), "▁▁▁");
+    }
+}
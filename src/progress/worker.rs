@@ -0,0 +1,97 @@
+use crate::fmt;
+use fast_down::{ProgressEntry, WorkerId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerState {
+    /// 已发出请求, 还没有收到数据
+    Connecting,
+    Downloading,
+    /// 出错后等待重试
+    Retrying,
+    /// 长时间没有收到数据, 正在重新连接
+    TimedOut,
+    Finished,
+}
+
+impl WorkerState {
+    fn label(self) -> String {
+        match self {
+            Self::Connecting => t!("progress.worker-connecting"),
+            Self::Downloading => t!("progress.worker-downloading"),
+            Self::Retrying => t!("progress.worker-retrying"),
+            Self::TimedOut => t!("progress.worker-timeout"),
+            Self::Finished => t!("progress.worker-finished"),
+        }
+        .to_string()
+    }
+}
+
+/// `--progress detailed` 中显示的单个线程
+#[derive(Debug, Clone, PartialEq)]
+pub struct Worker {
+    pub state: WorkerState,
+    /// 当前连续下载的区间
+    pub range: Option<ProgressEntry>,
+    pub curr_size: u64,
+    pub prev_size: u64,
+    pub avg_speed: f64,
+    pub retries: u64,
+}
+
+impl Default for Worker {
+    fn default() -> Self {
+        Self {
+            state: WorkerState::Connecting,
+            range: None,
+            curr_size: 0,
+            prev_size: 0,
+            avg_speed: 0.0,
+            retries: 0,
+        }
+    }
+}
+
+impl Worker {
+    /// 与当前区间相接时延长区间, 否则视为开始下载新的区间
+    pub fn add(&mut self, p: ProgressEntry) {
+        self.state = WorkerState::Downloading;
+        self.curr_size += p.end - p.start;
+        match &mut self.range {
+            Some(range) if range.end == p.start => range.end = p.end,
+            range => *range = Some(p),
+        }
+    }
+
+    pub fn render(&self, id: WorkerId) -> String {
+        let range = self.range.as_ref().map_or("-".to_string(), |range| {
+            format!(
+                "{} - {}",
+                fmt::format_size(range.start as f64),
+                fmt::format_size(range.end as f64)
+            )
+        });
+        format!(
+            "  #{id:<3} {:<12} {range:<24} {:>10}/s  {}",
+            self.state.label(),
+            fmt::format_size(self.avg_speed),
+            t!("progress.worker-retries", count = self.retries),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_range() {
+        let mut worker = Worker::default();
+        worker.add(0..10);
+        worker.add(10..30);
+        assert_eq!(worker.range, Some(0..30));
+        worker.add(100..120);
+        assert_eq!(worker.range, Some(100..120));
+        assert_eq!(worker.curr_size, 50);
+        assert_eq!(worker.state, WorkerState::Downloading);
+    }
+}
@@ -0,0 +1,259 @@
+use bytes::Bytes;
+use fast_down::{ProgressEntry, PullResult, PullStream, Puller};
+use futures::{Stream, TryStream, TryStreamExt};
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+    time::{Duration, Instant},
+};
+use tokio::{sync::watch, time::Sleep};
+
+/// 测速窗口
+const SPEED_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Bucket {
+    /// 限速 (单位: B/s), 0 代表不限速
+    rate: u64,
+    /// 可用的令牌数, 为负数时代表欠下的令牌
+    tokens: f64,
+    last_refill: Instant,
+    /// 最近一个测速窗口的实际速度 (单位: B/s)
+    speed: f64,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PauseState {
+    /// 本次暂停开始的时间, 为 None 时未暂停
+    since: Option<Instant>,
+    /// 之前所有暂停的总时长
+    total: Duration,
+}
+
+/// 所有线程共享的令牌桶, 最多允许 1 秒的突发流量
+///
+/// 暂停时所有线程停止读取数据, 超时断开的连接在继续前不会重新连接
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    paused: Arc<watch::Sender<PauseState>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate,
+                tokens: rate as f64,
+                last_refill: now,
+                speed: 0.0,
+                window_start: now,
+                window_bytes: 0,
+            })),
+            paused: Arc::new(watch::Sender::new(PauseState::default())),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.borrow().since.is_some()
+    }
+
+    /// 返回状态是否改变
+    pub fn set_paused(&self, paused: bool) -> bool {
+        self.paused
+            .send_if_modified(|state| match (state.since, paused) {
+                (None, true) => {
+                    state.since = Some(Instant::now());
+                    true
+                }
+                (Some(since), false) => {
+                    state.total += since.elapsed();
+                    state.since = None;
+                    true
+                }
+                _ => false,
+            })
+    }
+
+    /// 累计暂停的时长, 包括正在进行的暂停
+    pub fn paused_time(&self) -> Duration {
+        let state = *self.paused.borrow();
+        state.total + state.since.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+
+    /// 等待继续下载
+    fn resumed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut rx = self.paused.subscribe();
+        async move {
+            // self.paused 持有 Sender, 因此 wait_for() 不会返回 Err
+            let _ = rx.wait_for(|state| state.since.is_none()).await;
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.bucket.lock().rate
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        let mut bucket = self.bucket.lock();
+        bucket.rate = rate;
+        bucket.tokens = bucket.tokens.min(rate as f64);
+    }
+
+    /// 最近的实际下载速度 (单位: B/s)
+    pub fn speed(&self) -> f64 {
+        self.bucket.lock().speed
+    }
+
+    /// 消耗 `bytes` 个令牌, 返回需要等待的时间
+    fn consume(&self, bytes: u64) -> Duration {
+        let mut bucket = self.bucket.lock();
+        let now = Instant::now();
+        bucket.window_bytes += bytes;
+        let window = now - bucket.window_start;
+        if window >= SPEED_WINDOW {
+            bucket.speed = bucket.window_bytes as f64 / window.as_secs_f64();
+            bucket.window_start = now;
+            bucket.window_bytes = 0;
+        }
+        let elapsed = now - bucket.last_refill;
+        bucket.last_refill = now;
+        if bucket.rate == 0 {
+            return Duration::ZERO;
+        }
+        let rate = bucket.rate as f64;
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(rate) - bytes as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        }
+    }
+}
+
+/// 按 [`RateLimiter`] 限制速度的下载器
+#[derive(Debug, Clone)]
+pub struct LimitedPuller<P> {
+    inner: P,
+    limiter: RateLimiter,
+}
+
+impl<P> LimitedPuller<P> {
+    pub fn new(inner: P, limiter: RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl<P: Puller> Puller for LimitedPuller<P> {
+    type Error = P::Error;
+    async fn pull(
+        &mut self,
+        range: Option<&ProgressEntry>,
+    ) -> PullResult<impl PullStream<Self::Error>, Self::Error> {
+        self.limiter.resumed().await;
+        let inner = self.inner.pull(range).await?;
+        Ok(LimitedStream {
+            inner,
+            limiter: self.limiter.clone(),
+            pending: None,
+            resumed: None,
+        })
+    }
+}
+
+struct LimitedStream<S> {
+    inner: S,
+    limiter: RateLimiter,
+    /// 等待令牌时暂存的数据
+    pending: Option<(Pin<Box<Sleep>>, Bytes)>,
+    /// 暂停时等待继续
+    resumed: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+impl<S> Stream for LimitedStream<S>
+where
+    S: TryStream<Ok = Bytes> + Unpin,
+{
+    type Item = Result<Bytes, S::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.resumed.is_none() && self.limiter.is_paused() {
+            self.resumed = Some(Box::pin(self.limiter.resumed()));
+        }
+        if let Some(resumed) = &mut self.resumed {
+            ready!(resumed.as_mut().poll(cx));
+            self.resumed = None;
+        }
+        if let Some((sleep, _)) = &mut self.pending {
+            ready!(sleep.as_mut().poll(cx));
+            let (_, chunk) = self.pending.take().unwrap();
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+        match ready!(self.inner.try_poll_next_unpin(cx)) {
+            Some(Ok(chunk)) => {
+                let wait = self.limiter.consume(chunk.len() as u64);
+                if wait.is_zero() {
+                    return Poll::Ready(Some(Ok(chunk)));
+                }
+                self.pending = Some((Box::pin(tokio::time::sleep(wait)), chunk));
+                self.poll_next(cx)
+            }
+            res => Poll::Ready(res),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_wait(wait: Duration, secs: f64) {
+        assert!(
+            (wait.as_secs_f64() - secs).abs() < 0.05,
+            "wait {wait:?}, expected {secs}s"
+        );
+    }
+
+    #[test]
+    fn test_burst() {
+        let limiter = RateLimiter::new(1000);
+        // 开始时允许 1 秒的突发流量
+        assert_eq!(limiter.consume(1000), Duration::ZERO);
+        assert_wait(limiter.consume(500), 0.5);
+        assert_wait(limiter.consume(500), 1.0);
+    }
+
+    #[test]
+    fn test_set_rate() {
+        let limiter = RateLimiter::new(1000);
+        limiter.set_rate(0);
+        assert_eq!(limiter.consume(u32::MAX as u64), Duration::ZERO);
+
+        let limiter = RateLimiter::new(1000);
+        // 降低限速时可用的令牌不超过新的限速
+        limiter.set_rate(100);
+        assert_eq!(limiter.consume(100), Duration::ZERO);
+        assert_wait(limiter.consume(100), 1.0);
+    }
+
+    #[test]
+    fn test_paused_time() {
+        let limiter = RateLimiter::new(0);
+        assert!(!limiter.set_paused(false));
+        assert!(limiter.set_paused(true));
+        assert!(!limiter.set_paused(true));
+        assert!(limiter.is_paused());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.set_paused(false));
+        let paused_time = limiter.paused_time();
+        assert!(paused_time >= Duration::from_millis(20));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(limiter.paused_time(), paused_time);
+    }
+}
@@ -0,0 +1,287 @@
+use bytes::Bytes;
+use fast_down::{
+    ProgressEntry, PullResult, PullStream, Puller, fast_puller::FastDownPuller, http::HttpError,
+};
+use futures::{Stream, TryStream, TryStreamExt};
+use parking_lot::Mutex;
+use reqwest::Client;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+    time::{Duration, Instant},
+};
+
+/// 采样窗口, 窗口内的字节数除以耗时即为一次速度采样
+const SAMPLE_WINDOW: Duration = Duration::from_millis(500);
+/// 速度的指数平滑系数
+const ALPHA: f64 = 0.7;
+/// 出错的镜像经过这么久后重新测速, 避免一次临时错误使镜像在整个下载过程中都不再被使用
+const RETEST_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct MirrorStats {
+    /// 单连接平均速度 (单位: B/s), None 代表尚未测速
+    speed: Option<f64>,
+    /// 正在使用此镜像的连接数
+    active: usize,
+    /// 最近一次出错的时间, 成功测速后清除
+    penalized_at: Option<Instant>,
+}
+
+impl MirrorStats {
+    /// 出错后的第一次采样直接作为速度, 不与出错时降低的速度平滑
+    fn record(&mut self, bytes: u64, elapsed: Duration) {
+        let sample = bytes as f64 / elapsed.as_secs_f64();
+        self.speed = Some(match self.speed {
+            Some(speed) if self.penalized_at.is_none() => speed * ALPHA + sample * (1.0 - ALPHA),
+            _ => sample,
+        });
+        self.penalized_at = None;
+    }
+
+    /// 出错时速度减半, 尚未测速的镜像视为速度为 0
+    fn penalize(&mut self, now: Instant) {
+        self.speed = Some(self.speed.unwrap_or(0.0) * 0.5);
+        self.penalized_at = Some(now);
+    }
+
+    /// 出错超过 [`RETEST_AFTER`] 的镜像视为尚未测速
+    fn speed_at(&self, now: Instant) -> Option<f64> {
+        match self.penalized_at {
+            Some(at) if now.saturating_duration_since(at) >= RETEST_AFTER => None,
+            _ => self.speed,
+        }
+    }
+}
+
+/// 尚未测速的镜像优先, 其次选择速度最快的, 速度相同时选择连接数最少的, 都相同时选择靠前的
+fn pick(stats: impl IntoIterator<Item = MirrorStats>, now: Instant) -> usize {
+    stats
+        .into_iter()
+        .enumerate()
+        .map(|(i, stats)| {
+            (
+                i,
+                stats.speed_at(now).unwrap_or(f64::INFINITY),
+                stats.active,
+            )
+        })
+        .reduce(|best, curr| {
+            if curr.1 > best.1 || (curr.1 == best.1 && curr.2 < best.2) {
+                curr
+            } else {
+                best
+            }
+        })
+        .map_or(0, |(i, _, _)| i)
+}
+
+#[derive(Debug)]
+struct MirrorSource {
+    puller: FastDownPuller,
+    stats: Mutex<MirrorStats>,
+}
+
+impl MirrorSource {
+    fn record(&self, bytes: u64, elapsed: Duration) {
+        self.stats.lock().record(bytes, elapsed);
+    }
+
+    fn penalize(&self) {
+        self.stats.lock().penalize(Instant::now());
+    }
+}
+
+/// 从多个内容相同的镜像拉取数据
+///
+/// 每次拉取时选择单连接速度最快的镜像, 尚未测速的镜像优先, 速度相同时选择连接数最少的镜像;
+/// 出错的镜像速度减半, 一段时间后重新测速
+#[derive(Debug)]
+pub struct MirrorPuller {
+    sources: Vec<Arc<MirrorSource>>,
+    /// 每个线程按需克隆的下载器, 避免为用不到的镜像创建连接
+    pullers: Vec<Option<FastDownPuller>>,
+}
+
+impl MirrorPuller {
+    /// 第一个下载器为主链接, 不分片下载时只使用主链接
+    pub fn new(pullers: Vec<FastDownPuller>) -> Self {
+        let len = pullers.len();
+        Self {
+            sources: pullers
+                .into_iter()
+                .map(|puller| {
+                    Arc::new(MirrorSource {
+                        puller,
+                        stats: Mutex::new(MirrorStats::default()),
+                    })
+                })
+                .collect(),
+            pullers: vec![None; len],
+        }
+    }
+
+    fn pick(&self) -> usize {
+        pick(
+            self.sources.iter().map(|source| *source.stats.lock()),
+            Instant::now(),
+        )
+    }
+}
+
+impl Clone for MirrorPuller {
+    fn clone(&self) -> Self {
+        Self {
+            sources: self.sources.clone(),
+            pullers: vec![None; self.sources.len()],
+        }
+    }
+}
+
+impl Puller for MirrorPuller {
+    type Error = HttpError<Client>;
+    async fn pull(
+        &mut self,
+        range: Option<&ProgressEntry>,
+    ) -> PullResult<impl PullStream<Self::Error>, Self::Error> {
+        let index = if range.is_some() { self.pick() } else { 0 };
+        let source = self.sources[index].clone();
+        let puller = self.pullers[index].get_or_insert_with(|| source.puller.clone());
+        let guard = ActiveGuard::new(source);
+        match puller.pull(range).await {
+            Ok(inner) => Ok(MeasuredStream {
+                guard,
+                inner,
+                window_start: Instant::now(),
+                window_bytes: 0,
+            }),
+            Err(err) => {
+                guard.source.penalize();
+                Err(err)
+            }
+        }
+    }
+}
+
+/// 记录镜像的连接数, 被丢弃时自动减少
+struct ActiveGuard {
+    source: Arc<MirrorSource>,
+}
+
+impl ActiveGuard {
+    fn new(source: Arc<MirrorSource>) -> Self {
+        source.stats.lock().active += 1;
+        Self { source }
+    }
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.source.stats.lock().active -= 1;
+    }
+}
+
+struct MeasuredStream<S> {
+    guard: ActiveGuard,
+    inner: S,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl<S> Stream for MeasuredStream<S>
+where
+    S: TryStream<Ok = Bytes> + Unpin,
+{
+    type Item = Result<Bytes, S::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let res = ready!(self.inner.try_poll_next_unpin(cx));
+        match &res {
+            Some(Ok(chunk)) => {
+                self.window_bytes += chunk.len() as u64;
+                let elapsed = self.window_start.elapsed();
+                if elapsed >= SAMPLE_WINDOW {
+                    self.guard.source.record(self.window_bytes, elapsed);
+                    self.window_start = Instant::now();
+                    self.window_bytes = 0;
+                }
+            }
+            Some(Err(_)) => self.guard.source.penalize(),
+            None if self.window_bytes > 0 => {
+                let elapsed = self.window_start.elapsed();
+                self.guard.source.record(self.window_bytes, elapsed);
+                self.window_bytes = 0;
+            }
+            None => {}
+        }
+        Poll::Ready(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(speed: Option<f64>, active: usize) -> MirrorStats {
+        MirrorStats {
+            speed,
+            active,
+            penalized_at: None,
+        }
+    }
+
+    fn pick<const N: usize>(stats: [MirrorStats; N]) -> usize {
+        super::pick(stats, Instant::now())
+    }
+
+    #[test]
+    fn test_pick() {
+        // 尚未测速的镜像优先, 即使连接数更多
+        assert_eq!(pick([stats(Some(1e6), 0), stats(None, 5)]), 1);
+        // 其次选择速度最快的
+        assert_eq!(pick([stats(Some(1e5), 0), stats(Some(1e6), 3)]), 1);
+        // 速度相同时选择连接数最少的
+        assert_eq!(pick([stats(None, 2), stats(None, 1), stats(None, 1)]), 1);
+        assert_eq!(pick([stats(Some(1e6), 4), stats(Some(1e6), 2)]), 1);
+        assert_eq!(pick([]), 0);
+    }
+
+    #[test]
+    fn test_penalize() {
+        let now = Instant::now();
+        let mut mirror = stats(Some(1000.0), 0);
+        mirror.penalize(now);
+        assert_eq!(mirror.speed, Some(500.0));
+        let mut mirror = stats(None, 0);
+        mirror.penalize(now);
+        assert_eq!(mirror.speed, Some(0.0));
+        // 出错的镜像不再优先于尚未测速或更快的镜像
+        assert_eq!(pick([mirror, stats(Some(1.0), 0)]), 1);
+
+        let mut mirror = stats(None, 0);
+        mirror.record(1000, Duration::from_secs(1));
+        assert_eq!(mirror.speed, Some(1000.0));
+        mirror.record(2000, Duration::from_secs(1));
+        assert_eq!(mirror.speed, Some(1300.0));
+    }
+
+    #[test]
+    fn test_penalty_recovery() {
+        let now = Instant::now();
+        let mut mirror = stats(Some(1e6), 0);
+        mirror.penalize(now);
+        mirror.penalize(now);
+        let other = stats(Some(5e5), 0);
+        assert_eq!(super::pick([mirror, other], now), 1);
+        assert_eq!(super::pick([mirror, other], now + RETEST_AFTER / 2), 1);
+        // 一段时间后重新测速, 视为尚未测速的镜像优先
+        assert_eq!(super::pick([mirror, other], now + RETEST_AFTER), 0);
+
+        // 测速成功后清除惩罚, 速度不与出错时的速度平滑
+        mirror.record(2_000_000, Duration::from_secs(1));
+        assert_eq!(mirror.penalized_at, None);
+        assert_eq!(mirror.speed, Some(2e6));
+        assert_eq!(super::pick([mirror, other], now + RETEST_AFTER), 0);
+    }
+}
@@ -0,0 +1,138 @@
+use bytes::Bytes;
+use color_eyre::eyre::{Result, eyre};
+use fast_down::{AnyError, BoxPusher, ProgressEntry, Pusher};
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    sync::{Arc, Weak},
+};
+use tokio::sync::watch;
+
+/// 可以在下载过程中从外部刷新的写入器
+#[derive(Clone)]
+pub struct SharedPusher(Arc<Mutex<BoxPusher>>);
+
+impl Pusher for SharedPusher {
+    type Error = Box<dyn AnyError>;
+
+    fn push(&mut self, range: &ProgressEntry, content: Bytes) -> Result<(), (Self::Error, Bytes)> {
+        self.0.lock().push(range, content)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.lock().flush()
+    }
+}
+
+/// 所有下载任务共享的写入状态, 暂停时用于等待已读取的数据写入磁盘
+#[derive(Clone, Default)]
+pub struct WriteTracker {
+    /// 已读取但还未写入的字节数
+    pending: Arc<watch::Sender<u64>>,
+    /// 下载结束后写入器被释放, 对应的弱引用失效
+    pushers: Arc<Mutex<Vec<Weak<Mutex<BoxPusher>>>>>,
+}
+
+impl fmt::Debug for WriteTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteTracker")
+            .field("pending", &*self.pending.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+impl WriteTracker {
+    /// 登记一个下载任务的写入器
+    pub fn pusher(&self, pusher: BoxPusher) -> SharedPusher {
+        let pusher = Arc::new(Mutex::new(pusher));
+        let mut pushers = self.pushers.lock();
+        pushers.retain(|pusher| pusher.strong_count() > 0);
+        pushers.push(Arc::downgrade(&pusher));
+        SharedPusher(pusher)
+    }
+
+    /// 记录一个下载任务读取与写入的字节数
+    pub fn task(&self) -> TaskWrites {
+        TaskWrites {
+            pending: self.pending.clone(),
+            bytes: 0,
+        }
+    }
+
+    /// 等待已读取的数据全部写入后刷新所有写入器, 需要先停止读取新的数据
+    pub async fn flush(&self) -> Result<()> {
+        let mut rx = self.pending.subscribe();
+        // self.pending 持有 Sender, 因此 wait_for() 不会返回 Err
+        let _ = rx.wait_for(|pending| *pending == 0).await;
+        let pushers: Vec<_> = self
+            .pushers
+            .lock()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        tokio::task::spawn_blocking(move || {
+            for pusher in pushers {
+                pusher.lock().flush().map_err(|err| eyre!("{err:?}"))?;
+            }
+            Ok(())
+        })
+        .await?
+    }
+}
+
+/// 一个下载任务中已读取但还未写入的字节数, 任务结束时不再计入
+#[derive(Debug)]
+pub struct TaskWrites {
+    pending: Arc<watch::Sender<u64>>,
+    bytes: u64,
+}
+
+impl TaskWrites {
+    pub fn pulled(&mut self, len: u64) {
+        self.bytes += len;
+        self.pending.send_modify(|pending| *pending += len);
+    }
+
+    pub fn pushed(&mut self, len: u64) {
+        let len = len.min(self.bytes);
+        self.bytes -= len;
+        self.pending.send_modify(|pending| *pending -= len);
+    }
+}
+
+impl Drop for TaskWrites {
+    /// 中止或出错时已读取的数据不会再写入
+    fn drop(&mut self) {
+        let bytes = self.bytes;
+        self.pending.send_modify(|pending| *pending -= bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_flush_waits_for_pending() {
+        let tracker = WriteTracker::default();
+        let mut task = tracker.task();
+        task.pulled(100);
+        let flush = tokio::spawn({
+            let tracker = tracker.clone();
+            async move { tracker.flush().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!flush.is_finished());
+        task.pushed(60);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!flush.is_finished());
+        task.pushed(40);
+        flush.await.unwrap().unwrap();
+
+        // 任务结束后未写入的数据不再计入
+        task.pulled(10);
+        drop(task);
+        tracker.flush().await.unwrap();
+    }
+}
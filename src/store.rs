@@ -1,17 +1,69 @@
 use crate::fmt::add_prefix_to_lines;
-use crate::model::downloading::Downloading;
-use color_eyre::Result;
+use crate::{
+    model::{
+        downloading::{DownloadOptions, Downloading},
+        history::HistoryEntry,
+    },
+    utils::{dirs::state_dir, fingerprint::Piece},
+};
+use color_eyre::{Result, eyre::bail};
 use dashmap::DashMap;
 use fast_down::FileId;
 use parking_lot::Mutex;
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, Transaction, params};
 use std::fmt::Write;
 use std::path::Path;
 use std::{env, ffi::OsStr, path::PathBuf, sync::Arc, time::Duration};
 use tokio::fs;
 use url::Url;
 
-const CURRENT_DB_VERSION: u32 = 2;
+const CURRENT_DB_VERSION: u32 = 3;
+/// 没有 `user_version` 的旧数据库视为此版本
+const LEGACY_DB_VERSION: u32 = 2;
+const DB_NAME: &str = "fd-state.db";
+/// 项目模式下保存在下载目录中的数据库
+const PROJECT_DB_NAME: &str = ".fd-state.db";
+
+/// 需要告知用户的信息写入 `warnings`, 由调用方输出
+type Migration = fn(&Transaction, &mut Vec<String>) -> Result<()>;
+
+/// 第 i 项把数据库从版本 `LEGACY_DB_VERSION + i` 升级到下一个版本, 修改记录格式时在末尾追加
+const MIGRATIONS: &[Migration] = &[reencode_records];
+
+/// 版本 3: 选项改为带版本号的格式, 重新编码所有记录
+///
+/// 无法解码的记录原样移到 undecodable_downloads 表中, 以免丢失续传信息
+fn reencode_records(tx: &Transaction, warnings: &mut Vec<String>) -> Result<()> {
+    let rows: Vec<(String, Vec<u8>)> = tx
+        .prepare("SELECT path, data FROM downloads")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS undecodable_downloads (path TEXT PRIMARY KEY, data BLOB)",
+        [],
+    )?;
+    let mut update = tx.prepare("UPDATE downloads SET data = ? WHERE path = ?")?;
+    let mut move_aside =
+        tx.prepare("INSERT OR REPLACE INTO undecodable_downloads (path, data) VALUES (?, ?)")?;
+    let mut delete = tx.prepare("DELETE FROM downloads WHERE path = ?")?;
+    let mut undecodable = 0;
+    for (path, data) in rows {
+        match Downloading::load(&data) {
+            Some(entry) => {
+                update.execute(params![entry.dump(), path])?;
+            }
+            None => {
+                move_aside.execute(params![path, data])?;
+                delete.execute([path])?;
+                undecodable += 1;
+            }
+        }
+    }
+    if undecodable > 0 {
+        warnings.push(t!("msg.records-moved-aside", count = undecodable).to_string());
+    }
+    Ok(())
+}
 
 #[derive(Debug, Clone)]
 pub struct Store {
@@ -19,33 +71,192 @@ pub struct Store {
     db_path: PathBuf,
     /// file_path: record
     cache: Arc<DashMap<String, (bool, Downloading)>>,
+    /// 新记录是否同时写入 `<文件>.meta`
+    sidecar: bool,
 }
 
 impl Store {
-    async fn setup_db(path: &Path) -> Result<Arc<Mutex<Connection>>> {
-        let conn = Connection::open(path)?;
+    async fn setup_db(
+        path: &Path,
+        legacy: Option<(PathBuf, u32)>,
+    ) -> Result<(Arc<Mutex<Connection>>, Vec<String>)> {
+        let mut conn = Connection::open(path)?;
         conn.busy_timeout(Duration::from_millis(5000))?;
         conn.pragma_update(None, "journal_mode", "WAL")?;
         conn.pragma_update(None, "synchronous", "NORMAL")?;
+        let warnings = Self::init_db(&mut conn, legacy)?;
+
+        Ok((Arc::new(Mutex::new(conn)), warnings))
+    }
+
+    /// 建表并把数据库升级到当前版本, 新数据库会先导入 `legacy` 中的记录
+    ///
+    /// 返回需要告知用户的信息
+    fn init_db(conn: &mut Connection, legacy: Option<(PathBuf, u32)>) -> Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        let existed: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'downloads')",
+            [],
+            |row| row.get(0),
+        )?;
         conn.execute(
             "CREATE TABLE IF NOT EXISTS downloads (path TEXT PRIMARY KEY, data BLOB)",
             [],
         )?;
+        // 只新增表时不需要升级版本, 旧版本程序仍然可以打开数据库
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                elapsed INTEGER NOT NULL,
+                threads INTEGER NOT NULL,
+                retries INTEGER NOT NULL,
+                finished_at INTEGER NOT NULL,
+                error TEXT
+            )",
+            [],
+        )?;
+        let mut version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version == 0 && existed {
+            version = LEGACY_DB_VERSION;
+        } else if version == 0 {
+            version = CURRENT_DB_VERSION;
+            if let Some((legacy_path, legacy_version)) = legacy {
+                match Self::import_legacy(conn, &legacy_path) {
+                    Ok(()) => version = legacy_version,
+                    Err(err) => warnings.push(format!("{}: {err:#}", t!("err.database-load"))),
+                }
+            }
+            if version == CURRENT_DB_VERSION {
+                conn.pragma_update(None, "user_version", CURRENT_DB_VERSION)?;
+            }
+        }
+        Self::upgrade(conn, version, &mut warnings)?;
+        Ok(warnings)
+    }
 
-        Ok(Arc::new(Mutex::new(conn)))
+    /// 原样复制旧数据库中的记录, 旧数据库保持不变
+    fn import_legacy(conn: &mut Connection, legacy_path: &Path) -> Result<()> {
+        conn.execute(
+            "ATTACH DATABASE ? AS legacy",
+            [legacy_path.to_string_lossy()],
+        )?;
+        let res = conn.execute(
+            "INSERT OR IGNORE INTO downloads (path, data) SELECT path, data FROM legacy.downloads",
+            [],
+        );
+        conn.execute("DETACH DATABASE legacy", [])?;
+        res?;
+        Ok(())
     }
 
-    pub async fn new() -> Result<Self> {
-        let db_path = env::current_exe()
+    /// 依次执行 `version` 之后的迁移
+    fn upgrade(conn: &mut Connection, version: u32, warnings: &mut Vec<String>) -> Result<()> {
+        if version > CURRENT_DB_VERSION {
+            bail!(
+                "{}",
+                t!(
+                    "err.database-version",
+                    version = version,
+                    supported = CURRENT_DB_VERSION
+                )
+            );
+        }
+        if version == CURRENT_DB_VERSION {
+            return Ok(());
+        }
+        let tx = conn.transaction()?;
+        let start = version.saturating_sub(LEGACY_DB_VERSION) as usize;
+        for migration in &MIGRATIONS[start..] {
+            migration(&tx, warnings)?;
+        }
+        tx.pragma_update(None, "user_version", CURRENT_DB_VERSION)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn exe_dir() -> PathBuf {
+        env::current_exe()
             .ok()
             .and_then(|path| path.parent().map(|p| p.to_owned()))
             .unwrap_or(PathBuf::from("."))
-            .join(format!("fd-state-v{}.db", CURRENT_DB_VERSION));
+    }
+
+    fn default_path() -> PathBuf {
+        state_dir().unwrap_or_else(Self::exe_dir).join(DB_NAME)
+    }
+
+    /// 查找旧版本按版本号命名的数据库 `fd-state-v*.db`, 返回版本最高的一个
+    ///
+    /// 旧版本把数据库保存在程序所在目录或状态目录中
+    fn find_legacy_db() -> Option<(PathBuf, u32)> {
+        let mut found: Option<(PathBuf, u32)> = None;
+        for dir in [state_dir(), Some(Self::exe_dir())].into_iter().flatten() {
+            let Ok(read_dir) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in read_dir.flatten() {
+                let name = entry.file_name();
+                let Some(version) = name
+                    .to_str()
+                    .and_then(|name| name.strip_prefix("fd-state-v"))
+                    .and_then(|name| name.strip_suffix(".db"))
+                    .and_then(|version| version.parse::<u32>().ok())
+                else {
+                    continue;
+                };
+                if version <= CURRENT_DB_VERSION && found.as_ref().is_none_or(|(_, v)| version > *v)
+                {
+                    found = Some((entry.path(), version));
+                }
+            }
+        }
+        found
+    }
+
+    /// 数据库路径, 优先级为 指定的路径 > `project_dir` 中的 `.fd-state.db` > 状态目录
+    ///
+    /// `project` 为 true 时即使 `.fd-state.db` 不存在也会在 `project_dir` 中创建
+    pub fn locate(state_db: Option<&Path>, project_dir: &Path, project: bool) -> PathBuf {
+        if let Some(path) = state_db {
+            return path.to_path_buf();
+        }
+        let project_db = project_dir.join(PROJECT_DB_NAME);
+        if project || project_db.exists() {
+            return project_db;
+        }
+        Self::default_path()
+    }
+
+    /// 打开数据库, 提示信息直接输出到标准错误
+    pub async fn new(db_path: PathBuf) -> Result<Self> {
+        let (store, warnings) = Self::open(db_path).await?;
+        for warning in warnings {
+            eprintln!("{warning}");
+        }
+        Ok(store)
+    }
+
+    /// 打开数据库, 同时返回需要告知用户的信息, 由调用方输出以免打乱进度条
+    pub async fn open(db_path: PathBuf) -> Result<(Self, Vec<String>)> {
+        let legacy = if db_path == Self::default_path() && !fs::try_exists(&db_path).await? {
+            Self::find_legacy_db()
+        } else {
+            None
+        };
+
+        if let Some(parent) = db_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let (db, warnings) = Self::setup_db(&db_path, legacy).await?;
 
         let store_instance = Self {
-            db: Self::setup_db(&db_path).await?,
+            db,
             cache: Arc::new(DashMap::new()),
             db_path,
+            sidecar: false,
         };
 
         store_instance.clean().await?;
@@ -68,7 +279,34 @@ impl Store {
             }
         });
 
-        Ok(store_instance)
+        Ok((store_instance, warnings))
+    }
+
+    /// 新记录同时写入 `<文件>.meta`, 已有的 `.meta` 无论是否开启都会保持同步
+    pub fn with_sidecar(mut self, sidecar: bool) -> Self {
+        self.sidecar = sidecar;
+        self
+    }
+
+    pub fn sidecar_path(file_path: impl AsRef<OsStr>) -> PathBuf {
+        let mut path = file_path.as_ref().to_os_string();
+        path.push(".meta");
+        PathBuf::from(path)
+    }
+
+    /// 读取 `<文件>.meta` 中的记录
+    pub fn load_sidecar(file_path: impl AsRef<OsStr>) -> Option<Downloading> {
+        let data = std::fs::read(Self::sidecar_path(file_path)).ok()?;
+        Downloading::load(&data)
+    }
+
+    /// 先写入临时文件再重命名, 避免中断时留下不完整的 `.meta`
+    fn write_sidecar(file_path: &str, data: &[u8]) -> std::io::Result<()> {
+        let path = Self::sidecar_path(file_path);
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, &path)
     }
 
     pub async fn clean(&self) -> Result<()> {
@@ -114,6 +352,7 @@ impl Store {
         file_size: u64,
         file_id: &FileId,
         url: Url,
+        options: DownloadOptions,
     ) -> Result<()> {
         let path_str = file_path.as_ref().to_string_lossy().to_string();
         let entry = Downloading {
@@ -124,17 +363,43 @@ impl Store {
             url: url.to_string(),
             progress: Vec::new(),
             elapsed: Duration::ZERO,
+            fingerprint: Vec::new(),
+            options,
         };
 
+        let data = entry.dump();
+        if self.sidecar {
+            Self::write_sidecar(&path_str, &data)?;
+        }
         let conn = self.db.lock();
         conn.execute(
             "INSERT OR REPLACE INTO downloads (path, data) VALUES (?, ?)",
-            params![path_str, entry.dump()],
+            params![path_str, data],
         )?;
         self.cache.insert(path_str, (false, entry));
         Ok(())
     }
 
+    /// 导入记录, `overwrite` 为 false 时跳过已有的记录, 返回是否写入
+    pub fn import_entry(
+        &self,
+        file_path: &str,
+        entry: &Downloading,
+        overwrite: bool,
+    ) -> Result<bool> {
+        let sql = if overwrite {
+            "INSERT OR REPLACE INTO downloads (path, data) VALUES (?, ?)"
+        } else {
+            "INSERT OR IGNORE INTO downloads (path, data) VALUES (?, ?)"
+        };
+        let conn = self.db.lock();
+        let changed = conn.execute(sql, params![file_path, entry.dump()])? > 0;
+        if changed {
+            self.cache.remove(file_path);
+        }
+        Ok(changed)
+    }
+
     pub fn get_all_entry(&self) -> Option<DashMap<String, Downloading>> {
         let entries = DashMap::new();
         let db = self.db.lock();
@@ -147,8 +412,10 @@ impl Store {
                 Ok((path, data))
             })
             .ok()?;
-        for row in rows.flatten() {
-            entries.insert(row.0, Downloading::load(&row.1)?);
+        for (path, data) in rows.flatten() {
+            if let Some(entry) = Downloading::load(&data) {
+                entries.insert(path, entry);
+            }
         }
         if entries.is_empty() {
             return None;
@@ -157,11 +424,27 @@ impl Store {
         Some(entries)
     }
 
+    /// 按路径排序的所有记录, `fd list` 中的序号从 1 开始对应此顺序
+    pub fn sorted_entries(&self) -> Vec<(String, Downloading)> {
+        let mut entries: Vec<_> = self
+            .get_all_entry()
+            .map(|entries| entries.into_iter().collect())
+            .unwrap_or_default();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// `<文件>.meta` 优先于数据库, 读取后会同步到数据库
     pub fn get_entry(&self, file_path: impl AsRef<OsStr>) -> Option<Downloading> {
         let path_str = file_path.as_ref().to_string_lossy();
         if let Some(e) = self.cache.get(path_str.as_ref()) {
             return Some(e.1.clone());
         }
+        if let Some(entry) = Self::load_sidecar(file_path.as_ref()) {
+            self.cache
+                .insert(path_str.to_string(), (true, entry.clone()));
+            return Some(entry);
+        }
         let conn = self.db.lock();
         let data: Vec<u8> = conn
             .query_row(
@@ -171,6 +454,10 @@ impl Store {
             )
             .ok()?;
         let entry = Downloading::load(&data)?;
+        // 之后由 static_flush 保持同步
+        if self.sidecar && Path::new(path_str.as_ref()).exists() {
+            let _ = Self::write_sidecar(&path_str, &data);
+        }
         self.cache
             .insert(path_str.to_string(), (false, entry.clone()));
         Some(entry)
@@ -194,9 +481,24 @@ impl Store {
         }
     }
 
+    pub fn update_fingerprint(&self, file_path: impl AsRef<OsStr>, fingerprint: Vec<Piece>) {
+        let path_str = file_path.as_ref().to_string_lossy();
+        if let Some(mut e) = self.cache.get_mut(path_str.as_ref()) {
+            e.0 = true;
+            e.1.fingerprint = fingerprint;
+        } else if let Some(mut entry) = self.get_entry(file_path.as_ref()) {
+            entry.fingerprint = fingerprint;
+            self.cache.insert(path_str.to_string(), (true, entry));
+        }
+    }
+
     pub fn remove_entry(&self, file_path: impl AsRef<OsStr>) -> Result<()> {
         let path_str = file_path.as_ref().to_string_lossy();
         self.cache.remove(path_str.as_ref());
+        match std::fs::remove_file(Self::sidecar_path(file_path.as_ref())) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
         let conn = self.db.lock();
         conn.execute(
             "DELETE FROM downloads WHERE path = ?",
@@ -219,6 +521,12 @@ impl Store {
         if dirty_items.is_empty() {
             return Ok(());
         }
+        for (path, data) in &dirty_items {
+            // 文件已被移走时不再写入, 避免留下孤立的 .meta
+            if Self::sidecar_path(path).exists() && Path::new(path).exists() {
+                Self::write_sidecar(path, data)?;
+            }
+        }
         let mut conn = conn.lock();
         let tx = conn.transaction()?;
         {
@@ -232,11 +540,65 @@ impl Store {
         Ok(())
     }
 
+    pub fn add_history(&self, entry: &HistoryEntry) -> Result<()> {
+        self.db.lock().execute(
+            "INSERT INTO history (url, path, size, elapsed, threads, retries, finished_at, error)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                entry.url,
+                entry.path,
+                entry.size as i64,
+                entry.elapsed.as_millis() as i64,
+                entry.threads as i64,
+                entry.retries as i64,
+                entry.finished_at,
+                entry.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// 按完成时间从新到旧返回, `limit` 为 None 时返回全部
+    pub fn history(&self, limit: Option<usize>, failed_only: bool) -> Result<Vec<HistoryEntry>> {
+        let sql = format!(
+            "SELECT url, path, size, elapsed, threads, retries, finished_at, error FROM history
+            {} ORDER BY id DESC LIMIT ?",
+            if failed_only {
+                "WHERE error IS NOT NULL"
+            } else {
+                ""
+            }
+        );
+        let limit = limit.map_or(-1, |limit| limit as i64);
+        let db = self.db.lock();
+        let entries = db
+            .prepare(&sql)?
+            .query_map([limit], |row| {
+                Ok(HistoryEntry {
+                    url: row.get(0)?,
+                    path: row.get(1)?,
+                    size: row.get::<_, i64>(2)? as u64,
+                    elapsed: Duration::from_millis(row.get::<_, i64>(3)? as u64),
+                    threads: row.get::<_, i64>(4)? as usize,
+                    retries: row.get::<_, i64>(5)? as u64,
+                    finished_at: row.get(6)?,
+                    error: row.get(7)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
+    }
+
     pub fn force_flush(&self) -> Result<()> {
         Self::static_flush(&self.db, &self.cache)
     }
 
-    pub fn display(&self, with_details: bool) -> std::result::Result<String, std::fmt::Error> {
+    /// `entries` 中的序号为 [`Store::sorted_entries`] 中的位置加 1
+    pub fn display(
+        &self,
+        entries: &[(usize, String, Downloading)],
+        with_details: bool,
+    ) -> std::result::Result<String, std::fmt::Error> {
         let mut content = String::new();
 
         if with_details {
@@ -253,15 +615,8 @@ impl Store {
 
         writeln!(&mut content, "----------------------")?;
 
-        let Some(entries) = self.get_all_entry() else {
-            return Ok(content);
-        };
-
-        for ref_multi in entries.iter() {
-            let path: &String = ref_multi.key();
-            let entry: &Downloading = ref_multi.value();
-
-            write!(&mut content, "| ")?;
+        for (index, path, entry) in entries {
+            write!(&mut content, "| [{}] ", index)?;
             writeln!(&mut content, "{}: {}", t!("db-display.file-path"), path)?;
 
             let downloading_display = entry.display(with_details)?;
@@ -280,3 +635,83 @@ impl Drop for Store {
         let _ = self.force_flush();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Downloading {
+        Downloading {
+            url: "http://example.com/a.bin".to_string(),
+            file_name: "a.bin.fdpart".to_string(),
+            file_size: 100,
+            etag: None,
+            last_modified: None,
+            progress: vec![(0, 50)],
+            elapsed: Duration::from_secs(1),
+            fingerprint: Vec::new(),
+            options: DownloadOptions::default(),
+        }
+    }
+
+    fn user_version(conn: &Connection) -> u32 {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_upgrade_db() {
+        assert_eq!(
+            MIGRATIONS.len() as u32,
+            CURRENT_DB_VERSION - LEGACY_DB_VERSION
+        );
+
+        // 新数据库直接使用当前版本
+        let mut conn = Connection::open_in_memory().unwrap();
+        Store::init_db(&mut conn, None).unwrap();
+        assert_eq!(user_version(&conn), CURRENT_DB_VERSION);
+
+        // 没有 user_version 的旧数据库, 无法解码的记录会被移到 undecodable_downloads
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE downloads (path TEXT PRIMARY KEY, data BLOB)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO downloads (path, data) VALUES (?, ?), (?, ?)",
+            params![
+                "/a.bin.fdpart",
+                entry().dump(),
+                "/b.bin.fdpart",
+                vec![0xffu8]
+            ],
+        )
+        .unwrap();
+        let warnings = Store::init_db(&mut conn, None).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(user_version(&conn), CURRENT_DB_VERSION);
+        let rows: Vec<(String, Vec<u8>)> = conn
+            .prepare("SELECT path, data FROM downloads")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].0, "/a.bin.fdpart");
+        assert_eq!(Downloading::load(&rows[0].1), Some(entry()));
+        let undecodable: (String, Vec<u8>) = conn
+            .query_row("SELECT path, data FROM undecodable_downloads", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(undecodable, ("/b.bin.fdpart".to_string(), vec![0xff]));
+
+        // 更新版本的数据库无法打开
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", CURRENT_DB_VERSION + 1)
+            .unwrap();
+        assert!(Store::init_db(&mut conn, None).is_err());
+    }
+}
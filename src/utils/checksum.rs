@@ -0,0 +1,314 @@
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, bail},
+};
+use sha2::Digest;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgo {
+    Sha256,
+    Sha1,
+    Md5,
+    Blake3,
+}
+
+impl FromStr for ChecksumAlgo {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "sha256" | "sha-256" => Self::Sha256,
+            "sha1" | "sha-1" => Self::Sha1,
+            "md5" => Self::Md5,
+            "blake3" => Self::Blake3,
+            _ => bail!("不支持的校验算法: {s}"),
+        })
+    }
+}
+
+impl fmt::Display for ChecksumAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sha256 => "sha256",
+            Self::Sha1 => "sha1",
+            Self::Md5 => "md5",
+            Self::Blake3 => "blake3",
+        })
+    }
+}
+
+/// 期望的文件校验值, 格式为 `<算法>=<十六进制摘要>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algo: ChecksumAlgo,
+    pub digest: Vec<u8>,
+}
+
+impl FromStr for Checksum {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (algo, hex) = s
+            .split_once('=')
+            .with_context(|| format!("校验值格式错误: {s}"))?;
+        let algo: ChecksumAlgo = algo.trim().parse()?;
+        let digest = decode_hex(hex.trim()).with_context(|| format!("校验值格式错误: {s}"))?;
+        if digest.len() != algo.digest_len() {
+            bail!("{algo} 校验值长度错误: {s}");
+        }
+        Ok(Self { algo, digest })
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.algo, encode_hex(&self.digest))
+    }
+}
+
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha1(sha1::Sha1),
+    Md5(md5::Md5),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.update(data),
+            Self::Sha1(h) => h.update(data),
+            Self::Md5(h) => h.update(data),
+            Self::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Sha256(h) => h.finalize().to_vec(),
+            Self::Sha1(h) => h.finalize().to_vec(),
+            Self::Md5(h) => h.finalize().to_vec(),
+            Self::Blake3(h) => h.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+impl ChecksumAlgo {
+    /// 根据校验文件名推断算法, 如 `SHA256SUMS`、`a.iso.md5`
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        [
+            ("sha256", Self::Sha256),
+            ("sha1", Self::Sha1),
+            ("md5", Self::Md5),
+            ("blake3", Self::Blake3),
+            ("b3", Self::Blake3),
+        ]
+        .into_iter()
+        .find_map(|(pat, algo)| name.contains(pat).then_some(algo))
+    }
+
+    fn from_digest_len(len: usize) -> Option<Self> {
+        match len {
+            32 => Some(Self::Sha256),
+            20 => Some(Self::Sha1),
+            16 => Some(Self::Md5),
+            _ => None,
+        }
+    }
+
+    fn digest_len(self) -> usize {
+        match self {
+            Self::Sha256 | Self::Blake3 => 32,
+            Self::Sha1 => 20,
+            Self::Md5 => 16,
+        }
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            Self::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            Self::Md5 => Hasher::Md5(md5::Md5::new()),
+            Self::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    /// 在阻塞线程中计算整个文件的摘要
+    pub async fn digest_file(self, path: PathBuf) -> io::Result<Vec<u8>> {
+        tokio::task::spawn_blocking(move || {
+            let mut file = File::open(path)?;
+            let mut hasher = self.hasher();
+            let mut buf = vec![0; 1024 * 1024];
+            loop {
+                let len = file.read(&mut buf)?;
+                if len == 0 {
+                    break;
+                }
+                hasher.update(&buf[..len]);
+            }
+            Ok(hasher.finalize())
+        })
+        .await?
+    }
+}
+
+/// 在校验文件中查找 `file_name` 对应的校验值
+///
+/// 支持以下格式:
+/// 1. GNU 格式: `<hex>  <文件名>` 或 `<hex> *<文件名>`
+/// 2. BSD 格式: `SHA256 (<文件名>) = <hex>`
+/// 3. 只有一个 `<hex>` 的单文件校验文件
+///
+/// `hint` 为从校验文件名推断的算法, 为 None 时根据摘要长度推断
+pub fn find_checksum(
+    content: &str,
+    file_name: &str,
+    hint: Option<ChecksumAlgo>,
+) -> Option<Checksum> {
+    let lines: Vec<_> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    for line in &lines {
+        let (algo, name, hex) = if let Some((left, hex)) = line.rsplit_once(") = ")
+            && let Some((algo, name)) = left.split_once(" (")
+        {
+            (algo.parse().ok(), name, hex)
+        } else if let Some((hex, name)) = line.split_once(char::is_whitespace) {
+            (hint, name.trim_start().trim_start_matches('*'), hex)
+        } else {
+            continue;
+        };
+        if name.trim_start_matches("./") != file_name {
+            continue;
+        }
+        if let Some(checksum) = build_checksum(algo, hex) {
+            return Some(checksum);
+        }
+    }
+    match lines.as_slice() {
+        [hex] => build_checksum(hint, hex),
+        _ => None,
+    }
+}
+
+fn build_checksum(algo: Option<ChecksumAlgo>, hex: &str) -> Option<Checksum> {
+    let digest = decode_hex(hex.trim())?;
+    let algo = algo.or_else(|| ChecksumAlgo::from_digest_len(digest.len()))?;
+    (digest.len() == algo.digest_len()).then_some(Checksum { algo, digest })
+}
+
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.is_ascii() || !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(algo: ChecksumAlgo, data: &[u8]) -> Vec<u8> {
+        let mut hasher = algo.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    #[test]
+    fn test_checksum() {
+        let checksum: Checksum =
+            "SHA-256=BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"
+                .parse()
+                .unwrap();
+        assert_eq!(checksum.algo, ChecksumAlgo::Sha256);
+        assert_eq!(checksum.digest, digest(ChecksumAlgo::Sha256, b"abc"));
+        assert_eq!(
+            checksum.to_string(),
+            "sha256=ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            encode_hex(&digest(ChecksumAlgo::Md5, b"abc")),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            encode_hex(&digest(ChecksumAlgo::Sha1, b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            encode_hex(&digest(ChecksumAlgo::Blake3, b"abc")),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+
+        // 长度与算法不符
+        assert!("md5=ba7816bf".parse::<Checksum>().is_err());
+        // 非十六进制
+        assert!(
+            "md5=zz0150983cd24fb0d6963f7d28e17f72"
+                .parse::<Checksum>()
+                .is_err()
+        );
+        // 未知算法
+        assert!("crc32=352441c2".parse::<Checksum>().is_err());
+        // 缺少等号
+        assert!(
+            "900150983cd24fb0d6963f7d28e17f72"
+                .parse::<Checksum>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_find_checksum() {
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let expected = Some(Checksum {
+            algo: ChecksumAlgo::Sha256,
+            digest: decode_hex(sha256).unwrap(),
+        });
+        let gnu = format!("{}  b.iso\n{sha256} *a.iso\n", "0".repeat(64));
+        assert_eq!(find_checksum(&gnu, "a.iso", None), expected);
+        assert_eq!(find_checksum(&gnu, "c.iso", None), None);
+        let bsd = format!("SHA256 (./a.iso) = {sha256}\n");
+        assert_eq!(find_checksum(&bsd, "a.iso", None), expected);
+        let single = format!("{sha256}\n");
+        assert_eq!(
+            find_checksum(
+                &single,
+                "a.iso",
+                ChecksumAlgo::from_file_name("a.iso.sha256")
+            ),
+            expected
+        );
+        // 提示的算法与摘要长度不符
+        assert_eq!(
+            find_checksum(&single, "a.iso", Some(ChecksumAlgo::Md5)),
+            None
+        );
+        assert_eq!(
+            ChecksumAlgo::from_file_name("SHA1SUMS"),
+            Some(ChecksumAlgo::Sha1)
+        );
+        assert_eq!(
+            ChecksumAlgo::from_file_name("a.iso.b3"),
+            Some(ChecksumAlgo::Blake3)
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use std::{env, path::PathBuf};
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+}
+
+fn env_dir(key: &str) -> Option<PathBuf> {
+    env::var_os(key)
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+}
+
+/// 配置目录, 即 `$XDG_CONFIG_HOME/fast-down`, Windows 下为 `%APPDATA%\fast-down`
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| home_dir().map(|home| home.join(".config")))
+    };
+    base.map(|base| base.join("fast-down"))
+}
+
+/// 状态目录, 即 `$XDG_STATE_HOME/fast-down`, Windows 下为 `%LOCALAPPDATA%\fast-down`
+pub fn state_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else {
+        env_dir("XDG_STATE_HOME").or_else(|| home_dir().map(|home| home.join(".local/state")))
+    };
+    base.map(|base| base.join("fast-down"))
+}
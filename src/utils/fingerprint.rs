@@ -0,0 +1,98 @@
+use color_eyre::{Result, eyre::bail};
+use reqwest::{Client, StatusCode, header};
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+use url::Url;
+
+/// 每个采样区间的最大长度
+const SAMPLE_SIZE: u64 = 64 * 1024;
+/// 最多从多少个已完成的分段中采样
+const MAX_SEGMENTS: usize = 8;
+
+/// 采样区间 `(start, end, blake3)`
+pub type Piece = (u64, u64, [u8; 32]);
+
+/// 从已完成的分段中选出采样区间, 每个分段取开头和结尾各 [`SAMPLE_SIZE`] 字节
+///
+/// 分段过多时只取最长的 [`MAX_SEGMENTS`] 个
+pub fn sample_ranges(progress: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut segments: Vec<_> = progress.iter().filter(|(a, b)| a < b).copied().collect();
+    segments.sort_unstable_by_key(|(a, b)| std::cmp::Reverse(b - a));
+    segments.truncate(MAX_SEGMENTS);
+    segments.sort_unstable();
+    let mut ranges = Vec::with_capacity(segments.len() * 2);
+    for (start, end) in segments {
+        if end - start <= SAMPLE_SIZE * 2 {
+            ranges.push((start, end));
+        } else {
+            ranges.push((start, start + SAMPLE_SIZE));
+            ranges.push((end - SAMPLE_SIZE, end));
+        }
+    }
+    ranges
+}
+
+/// 读取本地文件中的采样区间并计算指纹
+pub async fn fingerprint_file(path: PathBuf, progress: Vec<(u64, u64)>) -> io::Result<Vec<Piece>> {
+    tokio::task::spawn_blocking(move || {
+        let mut file = File::open(path)?;
+        let mut buf = Vec::new();
+        sample_ranges(&progress)
+            .into_iter()
+            .map(|(start, end)| {
+                buf.resize((end - start) as usize, 0);
+                file.seek(SeekFrom::Start(start))?;
+                file.read_exact(&mut buf)?;
+                Ok((start, end, *blake3::hash(&buf).as_bytes()))
+            })
+            .collect()
+    })
+    .await?
+}
+
+/// 重新下载采样区间, 判断远程文件是否与指纹一致
+pub async fn verify_remote(client: &Client, url: &Url, pieces: &[Piece]) -> Result<bool> {
+    for (start, end, hash) in pieces {
+        let resp = client
+            .get(url.clone())
+            .header(header::RANGE, format!("bytes={start}-{}", end - 1))
+            .send()
+            .await?;
+        if resp.status() != StatusCode::PARTIAL_CONTENT {
+            bail!("服务器不支持 Range 请求: {}", resp.status());
+        }
+        let bytes = resp.bytes().await?;
+        if bytes.len() as u64 != end - start || blake3::hash(&bytes).as_bytes() != hash {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_ranges() {
+        assert_eq!(sample_ranges(&[]), vec![]);
+        assert_eq!(sample_ranges(&[(0, 100)]), vec![(0, 100)]);
+        assert_eq!(
+            sample_ranges(&[(0, 1 << 20), (2 << 20, (2 << 20) + 1000)]),
+            vec![
+                (0, SAMPLE_SIZE),
+                ((1 << 20) - SAMPLE_SIZE, 1 << 20),
+                (2 << 20, (2 << 20) + 1000),
+            ]
+        );
+        // 只保留最长的分段, 并按位置排序
+        let progress: Vec<_> = (0..20).map(|i| (i * 1000, i * 1000 + i)).collect();
+        let ranges = sample_ranges(&progress);
+        assert_eq!(ranges.len(), MAX_SEGMENTS);
+        assert_eq!(ranges.first(), Some(&(12000, 12012)));
+        assert_eq!(ranges.last(), Some(&(19000, 19019)));
+    }
+}
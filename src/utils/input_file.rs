@@ -0,0 +1,88 @@
+use color_eyre::{Result, eyre::ContextCompat};
+
+/// 输入文件中的一个下载任务
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputEntry {
+    pub url: String,
+    /// 同一行中以 TAB 分隔的其余 URL, 视为镜像
+    pub mirrors: Vec<String>,
+    /// 紧跟在 URL 后、以空白开头的 `key=value` 选项行
+    pub options: Vec<(String, String)>,
+}
+
+/// 解析 aria2 风格的输入文件
+///
+/// 1. 每个非空白开头的行是一个 URL
+/// 2. 以空白开头的行是上一个 URL 的选项，格式为 `key=value`
+/// 3. 空行与 `#` 开头的行会被忽略
+pub fn parse_input_file(content: &str) -> Result<Vec<InputEntry>> {
+    let mut entries: Vec<InputEntry> = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            let entry = entries
+                .last_mut()
+                .with_context(|| format!("输入文件第 {} 行: 选项前缺少 URL", i + 1))?;
+            let (key, value) = trimmed
+                .split_once('=')
+                .with_context(|| format!("输入文件第 {} 行: 选项格式错误: {trimmed}", i + 1))?;
+            entry
+                .options
+                .push((key.trim().to_string(), value.trim().to_string()));
+        } else {
+            let mut urls = trimmed.split('\t').map(str::trim).filter(|s| !s.is_empty());
+            entries.push(InputEntry {
+                // trimmed 非空, 因此至少有一个 URL
+                url: urls.next().unwrap_or_default().to_string(),
+                mirrors: urls.map(str::to_string).collect(),
+                options: Vec::new(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input_file() {
+        let content = "\
+# 注释
+https://example.com/a.iso
+  out=b.iso
+\tdir=/tmp/downloads
+
+https://example.com/c.zip\thttps://mirror.example.com/c.zip
+  header=Cookie: a=b
+";
+        let entries = parse_input_file(content).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                InputEntry {
+                    url: "https://example.com/a.iso".to_string(),
+                    mirrors: vec![],
+                    options: vec![
+                        ("out".to_string(), "b.iso".to_string()),
+                        ("dir".to_string(), "/tmp/downloads".to_string()),
+                    ],
+                },
+                InputEntry {
+                    url: "https://example.com/c.zip".to_string(),
+                    mirrors: vec!["https://mirror.example.com/c.zip".to_string()],
+                    options: vec![("header".to_string(), "Cookie: a=b".to_string())],
+                },
+            ]
+        );
+
+        // 选项前没有 URL
+        assert!(parse_input_file("  out=a.iso\n").is_err());
+        // 选项缺少等号
+        assert!(parse_input_file("https://example.com\n  out\n").is_err());
+    }
+}
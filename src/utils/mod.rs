@@ -1,3 +1,10 @@
+pub mod checksum;
 pub mod confirm;
+pub mod dirs;
+pub mod fingerprint;
+pub mod input_file;
 pub mod sanitize;
+pub mod schedule;
+pub mod select;
 pub mod space;
+pub mod thread_budget;
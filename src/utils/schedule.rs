@@ -0,0 +1,196 @@
+use crate::fmt::parse_rate;
+use chrono::{Local, Timelike};
+use color_eyre::{
+    Result,
+    eyre::{ContextCompat, bail},
+};
+use std::{str::FromStr, time::Duration};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const SECONDS_PER_DAY: u32 = MINUTES_PER_DAY * 60;
+/// 估算剩余时间时最多向后推算的天数
+const MAX_ETA_DAYS: u32 = 7;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScheduleRule {
+    /// 单位: 从零点起的分钟数
+    start: u32,
+    end: u32,
+    /// 单位: B/s, 0 代表不限速
+    rate: u64,
+}
+
+impl ScheduleRule {
+    fn contains(&self, minute: u32) -> bool {
+        match self.start.cmp(&self.end) {
+            std::cmp::Ordering::Less => (self.start..self.end).contains(&minute),
+            // 跨越零点, 如 18:00-08:00
+            std::cmp::Ordering::Greater => minute >= self.start || minute < self.end,
+            std::cmp::Ordering::Equal => true,
+        }
+    }
+}
+
+/// 按时间段限速, 如 `08:00-18:00=2M,18:00-08:00=unlimited`
+///
+/// 时间段重叠时以先写的为准, 不在任何时间段内时使用 `default_rate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    rules: Vec<ScheduleRule>,
+    pub default_rate: u64,
+}
+
+impl FromStr for Schedule {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rules = s
+            .split(',')
+            .map(|rule| {
+                let (range, rate) = rule
+                    .split_once('=')
+                    .with_context(|| format!("时间表格式错误: {rule}"))?;
+                let (start, end) = range
+                    .split_once('-')
+                    .with_context(|| format!("时间表格式错误: {rule}"))?;
+                Ok(ScheduleRule {
+                    start: parse_minute(start)?,
+                    end: parse_minute(end)?,
+                    rate: parse_rate(rate).with_context(|| format!("速度格式错误: {rate}"))?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            rules,
+            default_rate: 0,
+        })
+    }
+}
+
+/// 解析 `HH:MM`, 返回从零点起的分钟数
+fn parse_minute(time: &str) -> Result<u32> {
+    let time = time.trim();
+    let (hour, minute) = time
+        .split_once(':')
+        .with_context(|| format!("时间格式错误: {time}"))?;
+    let (hour, minute): (u32, u32) = (hour.parse()?, minute.parse()?);
+    if hour > 24 || minute >= 60 || hour * 60 + minute > MINUTES_PER_DAY {
+        bail!("时间格式错误: {time}");
+    }
+    Ok((hour * 60 + minute) % MINUTES_PER_DAY)
+}
+
+/// 当前本地时间, 单位: 从零点起的秒数
+pub fn now_seconds() -> u32 {
+    Local::now().num_seconds_from_midnight()
+}
+
+impl Schedule {
+    /// `second` 为从零点起的秒数
+    pub fn rate_at(&self, second: u32) -> u64 {
+        let minute = second / 60 % MINUTES_PER_DAY;
+        self.rules
+            .iter()
+            .find(|rule| rule.contains(minute))
+            .map_or(self.default_rate, |rule| rule.rate)
+    }
+
+    /// 距离下一次可能改变限速的时间
+    pub fn next_change(&self, second: u32) -> Duration {
+        Duration::from_secs(self.seconds_to_next_change(second) as u64)
+    }
+
+    fn seconds_to_next_change(&self, second: u32) -> u32 {
+        self.rules
+            .iter()
+            .flat_map(|rule| [rule.start, rule.end])
+            .map(|minute| {
+                (minute * 60 + SECONDS_PER_DAY - second % SECONDS_PER_DAY - 1) % SECONDS_PER_DAY + 1
+            })
+            .min()
+            .unwrap_or(SECONDS_PER_DAY)
+    }
+
+    /// 按时间表估算剩余时间 (单位: 秒)
+    ///
+    /// `capacity` 为不限速时的下载速度, 每个时间段的速度取 `capacity` 与限速中较小的一个
+    pub fn eta(&self, remaining: u64, capacity: f64, second: u32) -> Option<u64> {
+        if capacity <= 0.0 {
+            return None;
+        }
+        let mut remaining = remaining as f64;
+        let mut second = second % SECONDS_PER_DAY;
+        let mut total = 0;
+        while total < MAX_ETA_DAYS * SECONDS_PER_DAY {
+            let speed = match self.rate_at(second) {
+                0 => capacity,
+                rate => capacity.min(rate as f64),
+            };
+            let span = self.seconds_to_next_change(second);
+            if remaining <= speed * span as f64 {
+                return Some(total as u64 + (remaining / speed).ceil() as u64);
+            }
+            remaining -= speed * span as f64;
+            total += span;
+            second = (second + span) % SECONDS_PER_DAY;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const M: u64 = 1024 * 1024;
+
+    fn at(hour: u32, minute: u32) -> u32 {
+        (hour * 60 + minute) * 60
+    }
+
+    #[test]
+    fn test_schedule_rate() {
+        let schedule: Schedule = "08:00-18:00=2M, 18:00-08:00=unlimited".parse().unwrap();
+        assert_eq!(schedule.rate_at(at(7, 59)), 0);
+        assert_eq!(schedule.rate_at(at(8, 0)), 2 * M);
+        assert_eq!(schedule.rate_at(at(17, 59)), 2 * M);
+        assert_eq!(schedule.rate_at(at(18, 0)), 0);
+        assert_eq!(schedule.rate_at(at(0, 0)), 0);
+        assert_eq!(schedule.next_change(at(7, 0)), Duration::from_secs(3600));
+        assert_eq!(
+            schedule.next_change(at(8, 0)),
+            Duration::from_secs(10 * 3600)
+        );
+        assert_eq!(
+            schedule.next_change(at(23, 0)),
+            Duration::from_secs(9 * 3600)
+        );
+
+        let mut schedule: Schedule = "9:30-24:00=1M".parse().unwrap();
+        schedule.default_rate = 5 * M;
+        assert_eq!(schedule.rate_at(at(9, 29)), 5 * M);
+        assert_eq!(schedule.rate_at(at(23, 59)), M);
+
+        assert!("08:00-18:00".parse::<Schedule>().is_err());
+        assert!("08:00=2M".parse::<Schedule>().is_err());
+        assert!("08:60-18:00=2M".parse::<Schedule>().is_err());
+        assert!("24:01-18:00=2M".parse::<Schedule>().is_err());
+        assert!("08:00-18:00=fast".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn test_schedule_eta() {
+        let schedule: Schedule = "08:00-18:00=1M,18:00-08:00=unlimited".parse().unwrap();
+        let capacity = 10.0 * M as f64;
+        // 夜间不限速
+        assert_eq!(schedule.eta(100 * M, capacity, at(20, 0)), Some(10));
+        // 白天限速 1M
+        assert_eq!(schedule.eta(100 * M, capacity, at(9, 0)), Some(100));
+        // 白天剩余 10 秒, 之后不限速
+        assert_eq!(
+            schedule.eta(100 * M, capacity, at(18, 0) - 10),
+            Some(10 + 9)
+        );
+        assert_eq!(schedule.eta(100 * M, 0.0, at(9, 0)), None);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::model::downloading::Downloading;
+use color_eyre::{Result, eyre::bail};
+use glob::{MatchOptions, Pattern};
+use std::{env, path::PathBuf};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// 按 `fd list` 中的序号、文件路径或通配符选择记录, 结果保持 `entries` 的顺序
+///
+/// 路径可以省略 `.fdpart` 后缀, 相对路径基于当前目录
+pub fn select_entries(
+    entries: Vec<(String, Downloading)>,
+    targets: &[String],
+) -> Result<Vec<(String, Downloading)>> {
+    let mut selected = vec![false; entries.len()];
+    for target in targets {
+        let matched = match_target(&entries, target)?;
+        if matched.is_empty() {
+            bail!("{}", t!("err.entry-not-found", target = target));
+        }
+        for index in matched {
+            selected[index] = true;
+        }
+    }
+    Ok(entries
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(entry, selected)| selected.then_some(entry))
+        .collect())
+}
+
+fn match_target(entries: &[(String, Downloading)], target: &str) -> Result<Vec<usize>> {
+    if let Ok(index) = target.parse::<usize>()
+        && let Some(index) = index.checked_sub(1)
+        && index < entries.len()
+    {
+        return Ok(vec![index]);
+    }
+    let pattern = if is_glob(target) {
+        let path = PathBuf::from(target);
+        let path = if path.is_absolute() {
+            path
+        } else {
+            env::current_dir()?.join(path)
+        };
+        Pattern::new(&path.to_string_lossy())?
+    } else {
+        let path = soft_canonicalize::soft_canonicalize(target)?;
+        Pattern::new(&Pattern::escape(&path.to_string_lossy()))?
+    };
+    Ok(entries
+        .iter()
+        .enumerate()
+        .filter(|(_, (path, _))| {
+            let name = path.strip_suffix(".fdpart").unwrap_or(path);
+            pattern.matches_with(path, MATCH_OPTIONS) || pattern.matches_with(name, MATCH_OPTIONS)
+        })
+        .map(|(index, _)| index)
+        .collect())
+}
+
+fn is_glob(target: &str) -> bool {
+    target.contains(['*', '?', '['])
+}
@@ -0,0 +1,106 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use tokio::sync::watch;
+
+/// 多个文件同时下载时共享的线程预算, 由正在下载的文件平分
+#[derive(Debug, Clone)]
+pub struct ThreadBudget {
+    total: Arc<AtomicUsize>,
+    active: Arc<watch::Sender<usize>>,
+}
+
+impl ThreadBudget {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total: Arc::new(AtomicUsize::new(total.max(1))),
+            active: Arc::new(watch::Sender::new(0)),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// 增减总线程数, 至少为 1, 返回新的总线程数
+    pub fn resize(&self, delta: isize) -> usize {
+        let total = self.total().saturating_add_signed(delta).max(1);
+        self.total.store(total, Ordering::Relaxed);
+        // 通知所有文件重新分配线程
+        self.active.send_modify(|_| {});
+        total
+    }
+
+    /// 登记一个正在下载的文件, 返回的 [`BudgetPermit`] 被丢弃时自动注销
+    pub fn acquire(&self) -> BudgetPermit {
+        self.active.send_modify(|active| *active += 1);
+        BudgetPermit {
+            total: self.total.clone(),
+            rx: self.active.subscribe(),
+            active: self.active.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BudgetPermit {
+    total: Arc<AtomicUsize>,
+    active: Arc<watch::Sender<usize>>,
+    rx: watch::Receiver<usize>,
+}
+
+impl BudgetPermit {
+    /// 当前应使用的线程数
+    pub fn threads(&self) -> usize {
+        share(self.total.load(Ordering::Relaxed), *self.rx.borrow())
+    }
+
+    /// 等待正在下载的文件数或总线程数变化, 返回新的线程数
+    pub async fn changed(&mut self) -> usize {
+        // self.active 持有 Sender, 因此 changed() 不会返回 Err
+        let _ = self.rx.changed().await;
+        self.threads()
+    }
+}
+
+impl Drop for BudgetPermit {
+    fn drop(&mut self) {
+        self.active.send_modify(|active| *active -= 1);
+    }
+}
+
+fn share(total: usize, active: usize) -> usize {
+    (total / active.max(1)).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_budget() {
+        let budget = ThreadBudget::new(32);
+        let a = budget.acquire();
+        assert_eq!(a.threads(), 32);
+        let b = budget.acquire();
+        let c = budget.acquire();
+        assert_eq!(a.threads(), 10);
+        assert_eq!(b.threads(), 10);
+        drop(c);
+        assert_eq!(a.threads(), 16);
+        drop(b);
+        assert_eq!(a.threads(), 32);
+
+        let budget = ThreadBudget::new(2);
+        let permits: Vec<_> = (0..4).map(|_| budget.acquire()).collect();
+        assert!(permits.iter().all(|p| p.threads() == 1));
+
+        let budget = ThreadBudget::new(4);
+        let a = budget.acquire();
+        assert_eq!(budget.resize(2), 6);
+        assert_eq!(a.threads(), 6);
+        assert_eq!(budget.resize(-10), 1);
+        assert_eq!(a.threads(), 1);
+    }
+}